@@ -47,7 +47,7 @@ pub fn comp(comp: Comp) -> u8 {
         Sub(RegM, RegD) => 0b100_0111,
         And(RegD, RegA) => 0b000_0000,
         And(RegD, RegM) => 0b100_0000,
-        Or(RegA, RegD) => 0b001_0101,
+        Or(RegD, RegA) => 0b001_0101,
         Or(RegD, RegM) => 0b101_0101,
         _ => panic!("Invalid operation encountered: {comp:?}"),
     }
@@ -110,21 +110,21 @@ impl FromStr for Instruction {
                     "A+1" => Add(RegA, One),
                     "D-1" => Sub(RegD, One),
                     "A-1" => Sub(RegA, One),
-                    "D+A" => Add(RegD, RegA),
+                    "D+A" | "A+D" => Add(RegD, RegA),
                     "D-A" => Sub(RegD, RegA),
                     "A-D" => Sub(RegA, RegD),
-                    "D&A" => And(RegD, RegA),
-                    "D|A" => Or(RegD, RegA),
+                    "D&A" | "A&D" => And(RegD, RegA),
+                    "D|A" | "A|D" => Or(RegD, RegA),
                     "M" => Literal(RegM),
                     "!M" => Not(RegM),
                     "-M" => Negative(RegM),
                     "M+1" => Add(RegM, One),
                     "M-1" => Sub(RegM, One),
-                    "D+M" => Add(RegD, RegM),
+                    "D+M" | "M+D" => Add(RegD, RegM),
                     "D-M" => Sub(RegD, RegM),
                     "M-D" => Sub(RegM, RegD),
-                    "D&M" => And(RegD, RegM),
-                    "D|M" => Or(RegD, RegM),
+                    "D&M" | "M&D" => And(RegD, RegM),
+                    "D|M" | "M|D" => Or(RegD, RegM),
                     i => unreachable!("{i}"),
                 };
 
@@ -5,6 +5,7 @@ use path::PathBuf;
 use std::{env, fs, io, path, process};
 
 use compiler::tokenize::lexer::{tokenize, LiteralKind::*, TokenKind::*};
+use compiler::tokenize::tokenize as tokenize_spanned;
 
 fn main() -> Result<()> {
     let args = env::args().skip(1).collect::<Vec<_>>();
@@ -17,6 +18,11 @@ fn main() -> Result<()> {
     for file in args {
         let input = fs::read_to_string(&file)?;
 
+        if let Some(err) = tokenize_spanned(&input).find_map(|token| token.err()) {
+            eprintln!("{}", err.render(&file, &input));
+            process::exit(1);
+        }
+
         let output = {
             let mut name = Path::new(&file).file_stem().unwrap().to_owned();
             name.push("T");
@@ -43,7 +49,7 @@ fn main() -> Result<()> {
                     _ => writeln!(to, "<identifier> {token_text} </identifier>")?,
                 },
                 Literal(Int) => writeln!(to, "<integerConstant> {token_text} </integerConstant>")?,
-                Literal(Str) => writeln!(
+                Literal(Str { .. }) => writeln!(
                     to,
                     "<stringConstant> {} </stringConstant>",
                     &token_text[1..token_text.len() - 1]
@@ -1,9 +1,19 @@
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, process};
 
-use compiler::compiler::{Compiler, Mode, Result, Source};
+use compiler::compiler::{Compiler, Mode, Source};
 
-fn main() -> Result {
-    let file = env::args().nth(1).unwrap_or_else(|| String::from("."));
+fn main() {
+    let mut file = String::from(".");
+    let mut vm_extensions = false;
+    let mut library = false;
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--vm-extensions" => vm_extensions = true,
+            "--library" => library = true,
+            _ => file = arg,
+        }
+    }
 
     let source = if PathBuf::from(&file).is_file() {
         Source::File(file)
@@ -11,8 +21,12 @@ fn main() -> Result {
         Source::Directory(file)
     };
 
-    let compiler = Compiler::new(source, Mode::Vm);
-    compiler.compile()?;
+    let mut compiler = Compiler::new(source, Mode::Vm);
+    compiler.set_vm_extensions(vm_extensions);
+    compiler.set_library(library);
 
-    Ok(())
+    if let Err(err) = compiler.compile() {
+        eprintln!("{err}");
+        process::exit(1);
+    }
 }
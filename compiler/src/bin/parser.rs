@@ -1,15 +1,17 @@
 use std::{env, process};
 
-use compiler::compiler::{Compiler, Mode, Result, Source};
+use compiler::compiler::{Compiler, Mode, Source};
 
-fn main() -> Result {
+fn main() {
     let file = env::args().nth(1).unwrap_or_else(|| {
         println!("help: parser <input jack file>");
         process::exit(1);
     });
 
     let compiler = Compiler::new(Source::File(file), Mode::Xml);
-    compiler.compile()?;
 
-    Ok(())
+    if let Err(err) = compiler.compile() {
+        eprintln!("{err}");
+        process::exit(1);
+    }
 }
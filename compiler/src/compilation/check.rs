@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::mem;
+
+use crate::diagnostic::Diagnostic;
+use crate::parse::ast;
+use crate::tokenize::Spanned;
+
+use super::declarations::Declarations;
+use super::{static_method_call, unresolved, Analyzer};
+
+/// The subroutine being checked.
+struct Subroutine {
+    name: String,
+    kind: ast::SubroutineKind,
+    r#type: ast::ReturnType,
+}
+
+/// Checks classes against the declarations of the program for mistakes the VM writer compiles
+/// without complaint:
+///
+/// - calls to subroutines that don't exist, or with the wrong number of arguments
+/// - methods called on a class or from a function, and functions called on an object,
+///   though a method called on its class with the object first is only a warning
+/// - `return` with a value in a `void` subroutine, or without one in another
+/// - `this` and fields used in a function
+/// - variables that aren't defined
+/// - `boolean` values assigned to `int` and `char` variables, and the other way round
+///
+/// Calls to classes it doesn't know are only checked if the declarations are of the
+/// whole program.
+pub struct Checker<'a> {
+    declarations: &'a Declarations,
+    class: String,
+    fields: HashMap<String, (ast::ClassVarKind, ast::Type)>,
+    locals: HashMap<String, ast::Type>,
+    subroutine: Option<Subroutine>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Analyzer for Checker<'_> {
+    type Result = Vec<Diagnostic>;
+
+    fn analyze(&mut self, tree: &ast::Ast) -> Self::Result {
+        self.check_class(&tree.class);
+        mem::take(&mut self.diagnostics)
+    }
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(declarations: &'a Declarations) -> Self {
+        Self {
+            declarations,
+            class: String::new(),
+            fields: HashMap::new(),
+            locals: HashMap::new(),
+            subroutine: None,
+            diagnostics: vec![],
+        }
+    }
+
+    fn check_class(&mut self, class: &ast::Class) {
+        self.class = class.name.clone();
+        self.fields = class
+            .vars
+            .iter()
+            .flat_map(|var| {
+                var.names
+                    .iter()
+                    .map(|name| (name.clone(), (var.kind, var.r#type.clone())))
+            })
+            .collect();
+
+        for sub in &class.subroutines {
+            self.locals = sub
+                .parameters
+                .iter()
+                .map(|p| (p.name.clone(), p.r#type.clone()))
+                .chain(sub.body.vars.iter().flat_map(|var| {
+                    var.names
+                        .iter()
+                        .map(|name| (name.clone(), var.r#type.clone()))
+                }))
+                .collect();
+
+            self.subroutine = Some(Subroutine {
+                name: format!("{}.{}", class.name, sub.name),
+                kind: sub.kind,
+                r#type: sub.r#type.clone(),
+            });
+
+            self.check_statements(&sub.body.statements);
+        }
+    }
+
+    fn check_statements(&mut self, statements: &[ast::Statement]) {
+        for s in statements {
+            self.check_statement(s);
+        }
+    }
+
+    fn check_statement(&mut self, statement: &ast::Statement) {
+        match statement {
+            ast::Statement::Let {
+                var,
+                accessor,
+                expression,
+            } => {
+                let var_type = self.variable(var);
+
+                if let Some(expr) = accessor {
+                    self.expression(expr);
+                }
+
+                let value_type = self.expression(expression);
+
+                if let (Some(var_type), Some(value_type), None) = (var_type, value_type, accessor) {
+                    if mismatched(&var_type, &value_type) {
+                        self.warning(
+                            format!(
+                                "`{var}` has type `{var_type}` but the value assigned has type `{value_type}`"
+                            ),
+                            var,
+                        );
+                    }
+                }
+            }
+            ast::Statement::If {
+                cond,
+                statements,
+                r#else,
+            } => {
+                self.expression(cond);
+                self.check_statements(statements);
+
+                if let Some(statements) = r#else {
+                    self.check_statements(statements);
+                }
+            }
+            ast::Statement::While { cond, body } => {
+                self.expression(cond);
+                self.check_statements(body);
+            }
+            ast::Statement::Do { subroutine_call } => {
+                self.call(subroutine_call);
+            }
+            ast::Statement::Return { value, span } => {
+                if let Some(expr) = value {
+                    self.expression(expr);
+                }
+
+                let sub = self.subroutine.as_ref().unwrap();
+                let name = &sub.name;
+
+                match (&sub.r#type, value) {
+                    (ast::ReturnType::Void, Some(_)) => {
+                        let message = format!("`return` with a value in `{name}`, which is `void`");
+                        self.diagnostics.push(Diagnostic::new(message, *span));
+                    }
+                    (ast::ReturnType::Type(t), None) => {
+                        let message =
+                            format!("`return` without a value in `{name}`, which returns `{t}`");
+                        self.diagnostics.push(Diagnostic::warning(message, *span));
+                    }
+                    _ => {}
+                }
+            }
+            ast::Statement::Error => {}
+        }
+    }
+
+    /// Checks an expression, returning its type if it's known.
+    fn expression(&mut self, expr: &ast::Expression) -> Option<ast::Type> {
+        let mut r#type = self.term(&expr.0);
+
+        for (op, term) in &expr.1 {
+            let right = self.term(term);
+
+            r#type = match op {
+                ast::Op::Plus | ast::Op::Minus | ast::Op::Star | ast::Op::Div => {
+                    Some(ast::Type::Int)
+                }
+                ast::Op::Lt | ast::Op::Gt | ast::Op::Eq => Some(ast::Type::Boolean),
+                ast::Op::And | ast::Op::Or => match (r#type, right) {
+                    (Some(ast::Type::Boolean), Some(ast::Type::Boolean)) => {
+                        Some(ast::Type::Boolean)
+                    }
+                    (
+                        Some(ast::Type::Int | ast::Type::Char),
+                        Some(ast::Type::Int | ast::Type::Char),
+                    ) => Some(ast::Type::Int),
+                    _ => None,
+                },
+            };
+        }
+
+        r#type
+    }
+
+    fn term(&mut self, term: &ast::Term) -> Option<ast::Type> {
+        match term {
+            ast::Term::Int(_) => Some(ast::Type::Int),
+            ast::Term::Str(_) => Some(ast::Type::ClassName("String".to_owned())),
+            ast::Term::Keyword(k) => match **k {
+                ast::Keyword::True | ast::Keyword::False => Some(ast::Type::Boolean),
+                ast::Keyword::Null => None,
+                ast::Keyword::This => {
+                    if self.in_function() {
+                        self.error("`this` can't be used in a function", k);
+                    }
+
+                    Some(ast::Type::ClassName(self.class.clone()))
+                }
+            },
+            ast::Term::Var(name) => self.variable(name),
+            ast::Term::Index(name, expr) => {
+                self.variable(name);
+                self.expression(expr);
+                None
+            }
+            ast::Term::Call(call) => self.call(call),
+            ast::Term::Paren(expr) => self.expression(expr),
+            ast::Term::Unary(op, term) => {
+                let r#type = self.term(term);
+
+                match op {
+                    ast::UnaryOp::Minus => Some(ast::Type::Int),
+                    ast::UnaryOp::Not => r#type,
+                }
+            }
+        }
+    }
+
+    /// Checks a subroutine call, returning the type of its result if it's known.
+    fn call(&mut self, call: &ast::SubroutineCall) -> Option<ast::Type> {
+        let (receiver, name, expressions) = match call {
+            ast::SubroutineCall::Function { name, expressions } => (None, name, expressions),
+            ast::SubroutineCall::Method {
+                receiver,
+                name,
+                expressions,
+            } => (Some(receiver), name, expressions),
+        };
+
+        for e in expressions {
+            self.expression(e);
+        }
+
+        let (class, on_object) = match receiver {
+            None => (self.class.clone(), None),
+            Some(receiver) => match self.local_or_field(receiver) {
+                Some(ast::Type::ClassName(class)) => (class, Some(true)),
+                Some(r#type) => {
+                    let message = format!("`{receiver}` has type `{type}`, which has no methods");
+                    self.error(message, name);
+                    return None;
+                }
+                None => (receiver.to_string(), Some(false)),
+            },
+        };
+
+        let signature = match self.declarations.lookup(&class, name) {
+            Ok(Some(signature)) => signature,
+            Ok(None) => return None,
+            Err(err) => {
+                let at = receiver.filter(|_| on_object == Some(false));
+                self.diagnostics.push(unresolved(err, &class, at, name));
+                return None;
+            }
+        };
+
+        let method = signature.kind == ast::SubroutineKind::Method;
+        let mut parameters = signature.parameters;
+
+        match on_object {
+            None if method && self.in_function() => {
+                self.diagnostics.push(static_method_call(&class, name));
+            }
+            Some(true) if !method => self.error(
+                format!(
+                    "`{class}.{name}` is a {}, call it on the class",
+                    signature.kind
+                ),
+                name,
+            ),
+            // the VM can't tell it from a call on the first argument
+            Some(false) if method && expressions.len() == parameters + 1 => {
+                parameters += 1;
+                self.warning(
+                    format!(
+                        "`{class}.{name}` is a method, the first argument is passed as its object"
+                    ),
+                    name,
+                );
+            }
+            Some(false) if method => self.error(
+                format!("`{class}.{name}` is a method, call it on an object"),
+                name,
+            ),
+            _ => {}
+        }
+
+        if expressions.len() != parameters {
+            self.error(
+                format!(
+                    "`{class}.{name}` takes {} arguments but {} were given",
+                    parameters,
+                    expressions.len()
+                ),
+                name,
+            );
+        }
+
+        match &signature.r#type {
+            ast::ReturnType::Type(t) => Some(t.clone()),
+            ast::ReturnType::Void => None,
+        }
+    }
+
+    /// The type of a variable, reporting it if it isn't defined or can't be used here.
+    fn variable(&mut self, name: &Spanned<String>) -> Option<ast::Type> {
+        let r#type = self.local_or_field(name);
+
+        if r#type.is_none() && !self.fields.contains_key(name.as_str()) {
+            self.error(format!("symbol `{name}` is not defined"), name);
+        }
+
+        r#type
+    }
+
+    /// The type of a local or a field, reporting fields used in a function.
+    fn local_or_field(&mut self, name: &Spanned<String>) -> Option<ast::Type> {
+        if let Some(t) = self.locals.get(name.as_str()) {
+            return Some(t.clone());
+        }
+
+        let (kind, r#type) = self.fields.get(name.as_str()).cloned()?;
+
+        if matches!(kind, ast::ClassVarKind::Field) && self.in_function() {
+            self.error(format!("field `{name}` can't be used in a function"), name);
+            return None;
+        }
+
+        Some(r#type)
+    }
+
+    fn in_function(&self) -> bool {
+        self.subroutine
+            .as_ref()
+            .is_some_and(|s| s.kind == ast::SubroutineKind::Function)
+    }
+
+    fn error<T>(&mut self, message: impl Into<String>, at: &Spanned<T>) {
+        self.diagnostics.push(Diagnostic::new(message, at.span));
+    }
+
+    fn warning<T>(&mut self, message: impl Into<String>, at: &Spanned<T>) {
+        self.diagnostics.push(Diagnostic::warning(message, at.span));
+    }
+}
+
+/// Whether a value of type `found` is likely a mistake where `expected` is wanted.
+fn mismatched(expected: &ast::Type, found: &ast::Type) -> bool {
+    use ast::Type::*;
+
+    matches!(
+        (expected, found),
+        (Boolean, Int | Char) | (Int | Char, Boolean)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::parse::parse;
+    use crate::tokenize::tokenize;
+
+    use super::*;
+
+    fn check(classes: &[&str]) -> Vec<String> {
+        check_program(classes, false)
+    }
+
+    fn check_program(classes: &[&str], whole_program: bool) -> Vec<String> {
+        let asts: Vec<_> = classes
+            .iter()
+            .map(|input| {
+                let tokens = tokenize(input).collect::<Result<Vec<_>, _>>().unwrap();
+                parse(tokens).unwrap()
+            })
+            .collect();
+
+        let declarations = Declarations::new(&asts, whole_program);
+        let mut checker = Checker::new(&declarations);
+
+        asts.iter()
+            .flat_map(|ast| checker.analyze(ast))
+            .map(|d| format!("{}: {d}", d.severity))
+            .collect()
+    }
+
+    #[test]
+    fn checks_calls() {
+        let main = "class Main {
+    function void main() {
+        var Ball ball;
+        var int x;
+        let ball = Ball.new(1, 2);
+        do ball.move(3);
+        do ball.bounce();
+        do Ball.move();
+        do ball.create(1, 2);
+        do x.move();
+        do Output.printInt(1, 2);
+        do Output.print(1);
+        do Unknown.anything(1);
+        do helper(1);
+        do draw();
+        do Ball.move(ball, 1);
+        return;
+    }
+
+    function void helper() {
+        return;
+    }
+
+    method void draw() {
+        return;
+    }
+}";
+        let ball = "class Ball {
+    constructor Ball new(int x, int y) {
+        return this;
+    }
+
+    method void move(int dx) {
+        return;
+    }
+
+    function Ball create(int x, int y) {
+        return Ball.new(x, y);
+    }
+}";
+
+        assert_eq!(
+            check(&[main, ball]),
+            [
+                "error: 7:17: `Ball` has no subroutine `bounce`",
+                "error: 8:17: `Ball.move` is a method, call it on an object",
+                "error: 8:17: `Ball.move` takes 1 arguments but 0 were given",
+                "error: 9:17: `Ball.create` is a function, call it on the class",
+                "error: 10:14: `x` has type `int`, which has no methods",
+                "error: 11:19: `Output.printInt` takes 1 arguments but 2 were given",
+                "warning: 12:19: `Output.print` isn't part of the OS API",
+                "error: 14:12: `Main.helper` takes 0 arguments but 1 were given",
+                "error: 15:12: method `Main.draw` can't be called from a function, which has no `this`",
+                "warning: 16:17: `Ball.move` is a method, the first argument is passed as its object",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_subroutine_bodies() {
+        let main = "class Main {
+    field int count;
+    static boolean done;
+
+    function int main() {
+        var int x;
+        let x = done;
+        let done = x + 1;
+        let x = count;
+        let y = 1;
+        do Main.helper(this);
+        return;
+    }
+
+    function void helper(Main main) {
+        let done = (1 < 2) & true;
+        return done;
+    }
+
+    method int get() {
+        return count;
+    }
+}";
+
+        assert_eq!(
+            check(&[main]),
+            [
+                "warning: 7:13: `x` has type `int` but the value assigned has type `boolean`",
+                "warning: 8:13: `done` has type `boolean` but the value assigned has type `int`",
+                "error: 9:17: field `count` can't be used in a function",
+                "error: 10:13: symbol `y` is not defined",
+                "error: 11:24: `this` can't be used in a function",
+                "warning: 12:9: `return` without a value in `Main.main`, which returns `int`",
+                "error: 17:9: `return` with a value in `Main.helper`, which is `void`",
+            ]
+        );
+    }
+
+    #[test]
+    fn classes_replace_the_os() {
+        let math = "class Math {
+    function int multiply(int x, int y, int z) {
+        return 0;
+    }
+}";
+        let main = "class Main {
+    function void main() {
+        do Math.multiply(1, 2, 3);
+        do Math.abs(1);
+        do Output.printInt(Math.multiply(1, 2));
+        return;
+    }
+}";
+
+        assert_eq!(
+            check(&[math, main]),
+            [
+                "error: 4:17: `Math` has no subroutine `abs`",
+                "error: 5:33: `Math.multiply` takes 3 arguments but 2 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn os_helpers_are_warnings() {
+        let screen = "class Screen {
+    function void drawPixel(int x, int y) {
+        do Math.twoToThe(x);
+        do Memory.peek(y, x);
+        return;
+    }
+}";
+
+        assert_eq!(
+            check(&[screen]),
+            [
+                "warning: 3:17: `Math.twoToThe` isn't part of the OS API",
+                "error: 4:19: `Memory.peek` takes 1 arguments but 2 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_against_the_whole_program() {
+        let main = "class Main {
+    function void main() {
+        var Ball ball;
+        var Paddle paddle;
+        let ball = Ball.new();
+        do paddle.move();
+        do Bal.new();
+        do Output.println();
+        return;
+    }
+}";
+        let ball = "class Ball {
+    constructor Ball new() {
+        return this;
+    }
+}";
+
+        assert_eq!(
+            check_program(&[main, ball], true),
+            [
+                "error: 6:19: class `Paddle` is not defined",
+                "error: 7:12: `Bal` is not a variable or a class of the program",
+            ]
+        );
+        assert_eq!(check_program(&[main, ball], false), [] as [&str; 0]);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parse::{ast, parse};
+use crate::tokenize::tokenize;
+
+mod os;
+
+/// How a subroutine is called, and what it returns.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub kind: ast::SubroutineKind,
+    pub r#type: ast::ReturnType,
+    pub parameters: usize,
+}
+
+/// Why a call couldn't be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unresolved {
+    Class,
+    Subroutine,
+    /// A subroutine of an OS class the program doesn't replace, which isn't part of
+    /// its API but may be a helper of the OS the program runs with.
+    OsSubroutine,
+}
+
+/// The subroutines every class of a program declares, and those of the OS classes
+/// the program doesn't replace, collected before any code is generated.
+#[derive(Debug)]
+pub struct Declarations {
+    classes: HashMap<String, HashMap<String, Signature>>,
+    /// The OS classes the program doesn't replace.
+    os: HashSet<String>,
+    whole_program: bool,
+}
+
+impl Declarations {
+    /// The declarations of `classes`. If they are the `whole_program`, calls to any
+    /// other class are mistakes; otherwise they can't be checked.
+    pub fn new<'a>(classes: impl IntoIterator<Item = &'a ast::Ast>, whole_program: bool) -> Self {
+        let os = os::CLASSES.iter().map(|source| {
+            let tokens = tokenize(source).collect::<Result<Vec<_>, _>>();
+            parse(tokens.unwrap()).expect("the OS declarations parse")
+        });
+
+        let os: HashMap<_, _> = os.map(|ast| signatures(&ast.class)).collect();
+        let program: HashMap<_, _> = classes
+            .into_iter()
+            .map(|ast| signatures(&ast.class))
+            .collect();
+
+        Self {
+            os: os
+                .keys()
+                .filter(|class| !program.contains_key(*class))
+                .cloned()
+                .collect(),
+            classes: os.into_iter().chain(program).collect(),
+            whole_program,
+        }
+    }
+
+    /// The subroutine `class.name`, or `None` if the class isn't part of the
+    /// declarations and they aren't of the whole program.
+    pub fn lookup(&self, class: &str, name: &str) -> Result<Option<&Signature>, Unresolved> {
+        match self.classes.get(class) {
+            Some(subroutines) => match subroutines.get(name) {
+                Some(signature) => Ok(Some(signature)),
+                None if self.os.contains(class) => Err(Unresolved::OsSubroutine),
+                None => Err(Unresolved::Subroutine),
+            },
+            None if self.whole_program => Err(Unresolved::Class),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The name of a class and the signatures of its subroutines.
+fn signatures(class: &ast::Class) -> (String, HashMap<String, Signature>) {
+    let subroutines = class
+        .subroutines
+        .iter()
+        .map(|sub| {
+            let signature = Signature {
+                kind: sub.kind,
+                r#type: sub.r#type.clone(),
+                parameters: sub.parameters.len(),
+            };
+
+            (sub.name.clone(), signature)
+        })
+        .collect();
+
+    (class.name.clone(), subroutines)
+}
@@ -1,8 +1,15 @@
+use crate::diagnostic::Diagnostic;
 use crate::parse::ast;
+use crate::tokenize::Spanned;
+use declarations::Unresolved;
 
+pub use check::Checker;
+pub use declarations::Declarations;
 pub use vm::VMWriter;
 pub use xml::XMLAnalyzer;
 
+mod check;
+mod declarations;
 mod vm;
 mod xml;
 
@@ -10,3 +17,39 @@ pub trait Analyzer {
     type Result;
     fn analyze(&mut self, tree: &ast::Ast) -> Self::Result;
 }
+
+/// The error for calling a method of the class by its bare name in a function,
+/// which has no `this` to call it on.
+fn static_method_call(class: &str, name: &Spanned<String>) -> Diagnostic {
+    Diagnostic::new(
+        format!("method `{class}.{name}` can't be called from a function, which has no `this`"),
+        name.span,
+    )
+}
+
+/// The error for a call to a class or subroutine that isn't declared, or the warning
+/// for a subroutine of the OS that isn't in its API. `receiver` is the class name the
+/// call was made on, if there is one to point at.
+fn unresolved(
+    err: Unresolved,
+    class: &str,
+    receiver: Option<&Spanned<String>>,
+    name: &Spanned<String>,
+) -> Diagnostic {
+    match (err, receiver) {
+        (Unresolved::Class, Some(receiver)) => Diagnostic::new(
+            format!("`{class}` is not a variable or a class of the program"),
+            receiver.span,
+        ),
+        (Unresolved::Class, None) => {
+            Diagnostic::new(format!("class `{class}` is not defined"), name.span)
+        }
+        (Unresolved::Subroutine, _) => {
+            Diagnostic::new(format!("`{class}` has no subroutine `{name}`"), name.span)
+        }
+        (Unresolved::OsSubroutine, _) => Diagnostic::warning(
+            format!("`{class}.{name}` isn't part of the OS API"),
+            name.span,
+        ),
+    }
+}
@@ -1,10 +1,13 @@
+use std::collections::HashMap;
 use std::io;
 
+use crate::diagnostic::Diagnostic;
 use crate::parse::ast::{self, ClassVarKind, SubroutineKind};
+use crate::tokenize::Spanned;
 
 use self::vm_writer::*;
 
-use super::Analyzer;
+use super::{static_method_call, unresolved, Analyzer, Declarations};
 
 mod symbol_table;
 mod vm_writer;
@@ -17,6 +20,12 @@ pub struct VMWriter<'a, T> {
     symbols: SymbolTable,
     labels: Labels,
     class_name: Option<String>,
+    /// The kinds of the subroutines of the class, to tell how to call them.
+    subroutines: HashMap<String, SubroutineKind>,
+    /// The kind of the subroutine being compiled.
+    kind: SubroutineKind,
+    declarations: Option<&'a Declarations>,
+    vm_extensions: bool,
 }
 
 type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -36,9 +45,27 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
             class_name: None,
             symbols: Default::default(),
             labels: Default::default(),
+            subroutines: HashMap::new(),
+            kind: SubroutineKind::Function,
+            declarations: None,
+            vm_extensions: false,
         }
     }
 
+    /// Resolves calls to other classes against the `declarations` of the program,
+    /// failing on classes and subroutines that aren't declared. Without them, a call
+    /// on a name that isn't a variable is taken to be a call to a class of that name.
+    pub fn set_declarations(&mut self, declarations: &'a Declarations) {
+        self.declarations = Some(declarations);
+    }
+
+    /// Multiplies and divides with the `mul` and `div` commands, which the VM
+    /// translator supports but the standard VM language doesn't, instead of calling
+    /// `Math.multiply` and `Math.divide`.
+    pub fn set_vm_extensions(&mut self, enabled: bool) {
+        self.vm_extensions = enabled;
+    }
+
     fn compile(&mut self, ast: &ast::Ast) -> Result {
         self.class_name = Some(ast.class.name.clone());
         self.compile_class(&ast.class)
@@ -47,6 +74,12 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
     fn compile_class(&mut self, class: &ast::Class) -> Result {
         self.compile_class_var_dec(&class.vars);
 
+        self.subroutines = class
+            .subroutines
+            .iter()
+            .map(|sub| (sub.name.clone(), sub.kind))
+            .collect();
+
         for sub in &class.subroutines {
             self.compile_subroutine(sub, &class.name)?;
         }
@@ -65,6 +98,7 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
     fn compile_subroutine(&mut self, sub: &ast::Subroutine, class: &str) -> Result {
         self.symbols.start_subroutine();
         self.labels.clear();
+        self.kind = sub.kind;
 
         let locals = sub.body.vars.iter().flat_map(|v| &v.names).count();
 
@@ -199,7 +233,7 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
                 write_goto(&mut self.out, &l1)?; // goto L1
                 write_label(&mut self.out, &l2)?; // label L2
             }
-            ast::Statement::Return { value } => {
+            ast::Statement::Return { value, .. } => {
                 if let Some(expr) = value {
                     self.compile_expresssion(expr)?;
                 } else {
@@ -208,6 +242,7 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
 
                 write_return(&mut self.out)?;
             }
+            ast::Statement::Error => Err("cannot compile a statement that failed to parse")?,
         }
 
         Ok(())
@@ -299,6 +334,10 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
             ast::Op::Lt => write_arithmetic(&mut self.out, Arithmetic::Lt)?,
             ast::Op::Gt => write_arithmetic(&mut self.out, Arithmetic::Gt)?,
             ast::Op::Eq => write_arithmetic(&mut self.out, Arithmetic::Eq)?,
+            ast::Op::Star if self.vm_extensions => {
+                write_arithmetic(&mut self.out, Arithmetic::Mul)?
+            }
+            ast::Op::Div if self.vm_extensions => write_arithmetic(&mut self.out, Arithmetic::Div)?,
             ast::Op::Star => write_call(&mut self.out, "Math.multiply", 2)?,
             ast::Op::Div => write_call(&mut self.out, "Math.divide", 2)?,
         }
@@ -309,9 +348,23 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
     fn compile_subroutine_call(&mut self, call: &ast::SubroutineCall) -> Result {
         match call {
             ast::SubroutineCall::Function { name, expressions } => {
-                write_push(&mut self.out, Segment::Pointer, 0)?; // push THIS
-                let args = self.compile_expression_list(expressions)? + 1;
-                let class = self.class_name.as_ref().unwrap();
+                let class = self.class_name.clone().unwrap();
+
+                let args = match self.subroutines.get(name.as_str()) {
+                    Some(SubroutineKind::Method) if self.kind == SubroutineKind::Function => {
+                        return Err(static_method_call(&class, name).into());
+                    }
+                    Some(SubroutineKind::Method) => {
+                        write_push(&mut self.out, Segment::Pointer, 0)?; // push THIS
+                        self.compile_expression_list(expressions)? + 1
+                    }
+                    Some(_) => self.compile_expression_list(expressions)?,
+                    None => {
+                        let message = format!("`{class}` has no subroutine `{name}`");
+                        return Err(Diagnostic::new(message, name.span).into());
+                    }
+                };
+
                 write_call(&mut self.out, format_args!("{class}.{name}"), args)?;
             }
             ast::SubroutineCall::Method {
@@ -319,27 +372,44 @@ impl<'a, T: io::Write> VMWriter<'a, T> {
                 name,
                 expressions,
             } => {
-                let (receiver, args) = match self.symbols.get(receiver) {
-                    Some(symbol) => {
-                        write_push(&mut self.out, symbol.kind.into(), symbol.index as _)?;
-
-                        (
-                            symbol.r#type.to_string(),
-                            self.compile_expression_list(expressions)? + 1,
-                        )
+                let symbol = self
+                    .symbols
+                    .get(receiver)
+                    .map(|s| (s.kind, s.index, s.r#type.to_string()));
+
+                let class = match &symbol {
+                    Some((.., r#type)) => r#type.clone(),
+                    None => receiver.to_string(),
+                };
+
+                if let Some(declarations) = self.declarations {
+                    if let Err(err) = declarations.lookup(&class, name) {
+                        let at = symbol.is_none().then_some(receiver);
+                        let diagnostic = unresolved(err, &class, at, name);
+
+                        if diagnostic.is_error() {
+                            return Err(diagnostic.into());
+                        }
                     }
-                    None => (receiver.clone(), self.compile_expression_list(expressions)?),
+                }
+
+                let args = match symbol {
+                    Some((kind, index, _)) => {
+                        write_push(&mut self.out, kind.into(), index as _)?;
+                        self.compile_expression_list(expressions)? + 1
+                    }
+                    None => self.compile_expression_list(expressions)?,
                 };
 
-                write_call(&mut self.out, format_args!("{receiver}.{name}"), args)?;
+                write_call(&mut self.out, format_args!("{class}.{name}"), args)?;
             }
         }
 
         Ok(())
     }
 
-    fn undefined_symbol(&self, name: &str) -> String {
-        format!("symbol `{name}` is not defined")
+    fn undefined_symbol(&self, name: &Spanned<String>) -> Diagnostic {
+        Diagnostic::new(format!("symbol `{name}` is not defined"), name.span)
     }
 
     fn keyword(&mut self, keyword: &ast::Keyword) -> Result {
@@ -392,6 +462,10 @@ mod tests {
     use super::*;
 
     fn write(input: &str) -> String {
+        write_with(input, |_| {})
+    }
+
+    fn write_with(input: &str, configure: impl FnOnce(&mut VMWriter<Vec<u8>>)) -> String {
         let tokens = tokenize(input)
             .collect::<std::result::Result<Vec<_>, _>>()
             .unwrap();
@@ -399,6 +473,7 @@ mod tests {
         let mut out: Vec<u8> = vec![];
         let tree = parse(tokens).unwrap();
         let mut writer = VMWriter::new(&mut out);
+        configure(&mut writer);
         if let Err(err) = writer.analyze(&tree) {
             log!("{err}");
         }
@@ -406,6 +481,23 @@ mod tests {
         String::from_utf8(out).unwrap()
     }
 
+    /// The error compiling `input` against its own declarations, which are the
+    /// `whole_program` or not.
+    fn analyze_err(input: &str, whole_program: bool) -> Diagnostic {
+        let tokens = tokenize(input)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut out: Vec<u8> = vec![];
+        let tree = parse(tokens).unwrap();
+        let declarations = Declarations::new([&tree], whole_program);
+        let mut writer = VMWriter::new(&mut out);
+        writer.set_declarations(&declarations);
+        let err = writer.analyze(&tree).unwrap_err();
+
+        *err.downcast::<Diagnostic>().unwrap()
+    }
+
     #[test]
     fn test_seven() {
         let input = include_str!("../../../projects/11/Seven/Main.jack");
@@ -422,15 +514,49 @@ mod tests {
         assert_eq!(write(input), output);
     }
 
+    /// Compiles every class of a directory knowing the declarations of them all,
+    /// checking each against the .vm file next to it.
+    fn assert_program(dir: &str) {
+        let paths: Vec<_> = glob(&format!("{dir}/*.jack"))
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .collect();
+
+        let trees: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                let input = fs::read_to_string(path).unwrap();
+                let tokens = tokenize(&input)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .unwrap();
+                parse(tokens).unwrap()
+            })
+            .collect();
+
+        let declarations = Declarations::new(&trees, true);
+
+        for (path, tree) in paths.iter().zip(&trees) {
+            let mut out = vec![];
+            let mut writer = VMWriter::new(&mut out);
+            writer.set_declarations(&declarations);
+            writer.analyze(tree).unwrap();
+
+            let output = fs::read_to_string(path.with_extension("vm")).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), output);
+        }
+    }
+
     #[test]
     fn square() {
         for entry in glob("../projects/11/Square/*.jack").unwrap() {
             let path = entry.unwrap();
             let input = fs::read_to_string(&path).unwrap();
-            let output = fs::read_to_string(&path.with_extension("vm")).unwrap();
+            let output = fs::read_to_string(path.with_extension("vm")).unwrap();
 
             assert_eq!(write(&input), output);
         }
+
+        assert_program("../projects/11/Square");
     }
 
     #[test]
@@ -446,10 +572,12 @@ mod tests {
         for entry in glob("../projects/11/Pong/*.jack").unwrap() {
             let path = entry.unwrap();
             let input = fs::read_to_string(&path).unwrap();
-            let output = fs::read_to_string(&path.with_extension("vm")).unwrap();
+            let output = fs::read_to_string(path.with_extension("vm")).unwrap();
 
             assert_eq!(write(&input), output);
         }
+
+        assert_program("../projects/11/Pong");
     }
 
     #[test]
@@ -459,4 +587,142 @@ mod tests {
 
         assert_eq!(write(input), output);
     }
+
+    #[test]
+    fn vm_extensions() {
+        let input = "class Main {
+    function int f(int x, int y) {
+        return (x * y) / (x - y);
+    }
+}";
+
+        assert_eq!(
+            write_with(input, |writer| writer.set_vm_extensions(true)),
+            "function Main.f 0
+push argument 0
+push argument 1
+mul
+push argument 0
+push argument 1
+sub
+div
+return
+"
+        );
+        assert!(write(input).contains("call Math.multiply 2\n"));
+    }
+
+    #[test]
+    fn undefined_symbol() {
+        let input = "class Main {
+    function void main() {
+        var int a;
+        let a = b + 1;
+        return;
+    }
+}";
+        assert_eq!(
+            analyze_err(input, false).render("Main.jack", input),
+            "error: symbol `b` is not defined
+ --> Main.jack:4:17
+  |
+4 |         let a = b + 1;
+  |                 ^"
+        );
+    }
+
+    #[test]
+    fn unqualified_calls() {
+        let input = "class Main {
+    field int x;
+
+    constructor Main new() {
+        do reset();
+        return this;
+    }
+
+    method void reset() {
+        let x = twice(1);
+        return;
+    }
+
+    function int twice(int n) {
+        return helper(n) + n;
+    }
+
+    function int helper(int n) {
+        return n;
+    }
+}";
+
+        assert_eq!(
+            write(input),
+            "function Main.new 0
+push constant 1
+call Memory.alloc 1
+pop pointer 0
+push pointer 0
+call Main.reset 1
+pop temp 0
+push pointer 0
+return
+function Main.reset 0
+push argument 0
+pop pointer 0
+push constant 1
+call Main.twice 1
+pop this 0
+push constant 0
+return
+function Main.twice 0
+push argument 0
+call Main.helper 1
+push argument 0
+add
+return
+function Main.helper 0
+push argument 0
+return
+"
+        );
+    }
+
+    #[test]
+    fn method_call_from_function() {
+        let input = "class Main {
+    function void main() {
+        do draw();
+        do missing();
+        return;
+    }
+
+    method void draw() {
+        return;
+    }
+}";
+        assert_eq!(
+            analyze_err(input, false).to_string(),
+            "3:12: method `Main.draw` can't be called from a function, which has no `this`"
+        );
+    }
+
+    #[test]
+    fn unknown_classes_and_subroutines() {
+        let input = "class Main {
+    function void main() {
+        var Game game;
+        do game.run();
+        do Main.run();
+        do Outptu.println();
+        return;
+    }
+}";
+        assert_eq!(
+            [false, true].map(|whole_program| analyze_err(input, whole_program).to_string()),
+            [
+                "5:17: `Main` has no subroutine `run`",
+                "4:17: class `Game` is not defined",
+            ]
+        );
+    }
 }
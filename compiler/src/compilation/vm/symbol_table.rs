@@ -12,7 +12,6 @@ pub enum Kind {
 
 #[derive(Debug)]
 pub struct Symbol {
-    pub name: String,
     pub r#type: ast::Type,
     pub kind: Kind,
     pub index: usize,
@@ -50,7 +49,6 @@ impl SymbolTable {
         symbols.insert(
             name.to_owned(),
             Symbol {
-                name: name.to_owned(),
                 r#type: r#type.clone(),
                 kind,
                 index,
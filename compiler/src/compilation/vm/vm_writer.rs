@@ -26,6 +26,8 @@ pub enum Arithmetic {
     And,
     Or,
     Not,
+    Mul,
+    Div,
 }
 
 pub fn write_push(mut to: impl Write, seg: Segment, index: u16) -> Result {
@@ -94,6 +96,8 @@ impl fmt::Display for Arithmetic {
             And => "and",
             Or => "or",
             Not => "not",
+            Mul => "mul",
+            Div => "div",
         }
         .fmt(f)
     }
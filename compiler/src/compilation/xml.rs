@@ -75,7 +75,7 @@ impl<'a, T: io::Write> XMLWriter<'a, T> {
         for sub in subs {
             self.write("<subroutineDec>")?;
             self.level += 2;
-            self.keyword(&sub.kind)?;
+            self.keyword(sub.kind)?;
             self.return_type(&sub.r#type)?;
             self.ident(&sub.name)?;
             self.symbol('(')?;
@@ -188,7 +188,7 @@ impl<'a, T: io::Write> XMLWriter<'a, T> {
 
                 Ok(())
             }
-            ast::Statement::Return { value } => {
+            ast::Statement::Return { value, .. } => {
                 self.write("<returnStatement>")?;
                 self.level += 2;
 
@@ -204,6 +204,9 @@ impl<'a, T: io::Write> XMLWriter<'a, T> {
 
                 Ok(())
             }
+            ast::Statement::Error => Err(io::Error::other(
+                "cannot write a statement that failed to parse",
+            )),
         }
     }
 
@@ -4,8 +4,10 @@ use std::fs::{self, File};
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 
-use crate::compilation::{Analyzer, VMWriter, XMLAnalyzer};
-use crate::parse::parse;
+use crate::compilation::{Analyzer, Checker, Declarations, VMWriter, XMLAnalyzer};
+use crate::diagnostic::Diagnostic;
+use crate::parse::ast::Ast;
+use crate::parse::parse_recovering;
 use crate::tokenize::tokenize;
 
 pub enum Mode {
@@ -21,50 +23,188 @@ pub enum Source {
 pub struct Compiler {
     source: Source,
     mode: Mode,
+    vm_extensions: bool,
+    library: bool,
 }
 
 pub type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 impl Compiler {
     pub fn new(source: Source, mode: Mode) -> Self {
-        Self { source, mode }
+        Self {
+            source,
+            mode,
+            vm_extensions: false,
+            library: false,
+        }
+    }
+
+    /// Emits `mul` and `div` instead of calls to `Math`, see
+    /// `VMWriter::set_vm_extensions`.
+    pub fn set_vm_extensions(&mut self, enabled: bool) {
+        self.vm_extensions = enabled;
     }
 
+    /// Takes a directory to be a library, such as the OS, which calls classes it
+    /// doesn't declare, instead of the whole program.
+    pub fn set_library(&mut self, enabled: bool) {
+        self.library = enabled;
+    }
+
+    /// Compiles every file of the source. Errors in the code of any file stop the
+    /// compilation before anything is written, and are shown all together as
+    /// diagnostics.
+    ///
+    /// All files are parsed before any is compiled, so calls are resolved against
+    /// the declarations of every class. A directory is taken to be the whole program,
+    /// unless it's a library (see `set_library`), so calls to classes it doesn't
+    /// declare, other than the OS, are errors.
     pub fn compile(&self) -> Result {
-        match &self.source {
-            Source::File(file) => self.compile_file(file),
+        let files = match &self.source {
+            Source::File(file) => vec![file.clone()],
             Source::Directory(dir) => {
                 let path = PathBuf::from(dir).join("*.jack");
                 let path = path.to_str().unwrap();
 
-                for entry in glob(path)? {
-                    self.compile_file(entry?.to_str().unwrap())?;
+                glob(path)?
+                    .map(|entry| Ok(entry?.to_str().unwrap().to_owned()))
+                    .collect::<Result<_>>()?
+            }
+        };
+
+        let mut sources = vec![];
+
+        for name in files {
+            let input = fs::read_to_string(&name)?;
+            sources.push((name, input));
+        }
+
+        let mut asts = vec![];
+        let mut errors = vec![];
+
+        for (name, input) in &sources {
+            match parse_file(input) {
+                Ok(ast) => asts.push(ast),
+                Err(diagnostics) => errors.push(render(name, input, &diagnostics)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("\n\n").into());
+        }
+
+        let whole_program = matches!(self.source, Source::Directory(_)) && !self.library;
+        let declarations = Declarations::new(&asts, whole_program);
+
+        if let Mode::Vm = self.mode {
+            let mut checker = Checker::new(&declarations);
+
+            for ((name, input), ast) in sources.iter().zip(&asts) {
+                let (problems, warnings): (Vec<_>, Vec<_>) = checker
+                    .analyze(ast)
+                    .into_iter()
+                    .partition(Diagnostic::is_error);
+
+                if !warnings.is_empty() {
+                    eprintln!("{}\n", render(name, input, &warnings));
                 }
 
-                Ok(())
+                if !problems.is_empty() {
+                    errors.push(render(name, input, &problems));
+                }
+            }
+
+            if !errors.is_empty() {
+                return Err(errors.join("\n\n").into());
             }
         }
+
+        for ((name, input), ast) in sources.iter().zip(&asts) {
+            self.write(name, ast, &declarations).map_err(|err| {
+                match err.downcast::<Diagnostic>() {
+                    Ok(diagnostic) => render(name, input, &[*diagnostic]).into(),
+                    Err(err) => err,
+                }
+            })?;
+        }
+
+        Ok(())
     }
-    fn compile_file(&self, name: &str) -> Result {
-        let input = fs::read_to_string(name)?;
-        let tokens = tokenize(&input).collect::<Result<Vec<_>>>()?;
-        let ast = parse(tokens)?;
 
+    fn write(&self, name: &str, ast: &Ast, declarations: &Declarations) -> Result {
         match self.mode {
             Mode::Xml => {
                 let output = Path::new(name).with_extension("C.xml");
                 let mut output = BufWriter::new(File::create(output)?);
                 let mut analyzer = XMLAnalyzer::new(&mut output);
-                analyzer.analyze(&ast)?;
+                analyzer.analyze(ast)?;
             }
             Mode::Vm => {
                 let output = Path::new(name).with_extension("vm");
                 let mut output = BufWriter::new(File::create(output)?);
                 let mut vm_writer = VMWriter::new(&mut output);
-                vm_writer.analyze(&ast)?;
+                vm_writer.set_declarations(declarations);
+                vm_writer.set_vm_extensions(self.vm_extensions);
+                vm_writer.analyze(ast)?;
             }
         }
 
         Ok(())
     }
 }
+
+/// Parses a file, failing with every error found in it.
+fn parse_file(input: &str) -> std::result::Result<Ast, Vec<Diagnostic>> {
+    let tokens = tokenize(input)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|err| vec![err])?;
+
+    match parse_recovering(tokens) {
+        (ast, errors) if errors.is_empty() => Ok(ast),
+        (_, errors) => Err(errors),
+    }
+}
+
+fn render(name: &str, input: &str, diagnostics: &[Diagnostic]) -> String {
+    let rendered: Vec<_> = diagnostics.iter().map(|d| d.render(name, input)).collect();
+    rendered.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn compiles_a_library_directory() {
+        let dir = env::temp_dir().join(format!("jackc-os-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for entry in glob("../projects/12/*.jack").unwrap() {
+            let path = entry.unwrap();
+            fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+
+        let source = || Source::Directory(dir.to_str().unwrap().to_owned());
+
+        let err = Compiler::new(source(), Mode::Vm)
+            .compile()
+            .unwrap_err()
+            .to_string();
+
+        let mut compiler = Compiler::new(source(), Mode::Vm);
+        compiler.set_library(true);
+        let result = compiler.compile();
+        let compiled = dir.join("Sys.vm").exists();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        result.unwrap();
+        assert!(compiled);
+        assert!(
+            err.contains("`Main` is not a variable or a class of the program"),
+            "{err}"
+        );
+    }
+}
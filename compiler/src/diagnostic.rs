@@ -0,0 +1,193 @@
+use std::{error, fmt};
+
+use crate::tokenize::Span;
+
+/// An error, or a warning, at a place in a .jack file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(message, span)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Shows the error the way rustc does, with the line of `source` it is on and
+    /// its span underlined:
+    ///
+    /// ```text
+    /// error: symbol `x` is not defined
+    ///  --> Main.jack:3:13
+    ///   |
+    /// 3 |         let x = 1;
+    ///   |             ^
+    /// ```
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let Span { line, column, .. } = self.span;
+
+        let text = source.lines().nth(line - 1).unwrap_or_default();
+        let rest = text.chars().count().saturating_sub(column - 1);
+        let before: String = text.chars().take(column - 1).collect();
+        let spanned = source
+            .get(self.span.start..self.span.end)
+            .unwrap_or_default();
+        let width = spanned
+            .chars()
+            .take_while(|&c| c != '\n')
+            .count()
+            .clamp(1, rest.max(1));
+
+        let gutter = " ".repeat(line.to_string().len());
+
+        format!(
+            "{severity}: {message}\n\
+             {gutter}--> {path}:{line}:{column}\n\
+             {gutter} |\n\
+             {line} | {text}\n\
+             {gutter} | {pad}{carets}",
+            severity = self.severity,
+            message = self.message,
+            text = expand_tabs(text),
+            pad = " ".repeat(expand_tabs(&before).chars().count()),
+            carets = "^".repeat(width),
+        )
+    }
+}
+
+/// Shows tabs as 4 spaces, as rustc does, so the carets line up with the text.
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Span { line, column, .. } = self.span;
+        write!(f, "{line}:{column}: {}", self.message)
+    }
+}
+
+impl error::Error for Diagnostic {}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::parse::parse;
+    use crate::tokenize::tokenize;
+
+    fn parse_error(input: &str) -> String {
+        let tokens = tokenize(input).collect::<Result<Vec<_>, _>>().unwrap();
+
+        parse(tokens).unwrap_err().render("Main.jack", input)
+    }
+
+    #[test]
+    fn underlines_the_token() {
+        let input = "class Main {
+    function void main() {
+        do Output.printInt(1)
+    }
+}";
+
+        assert_eq!(
+            parse_error(input),
+            "error: expected `;`, found `}`
+ --> Main.jack:4:5
+  |
+4 |     }
+  |     ^"
+        );
+
+        let input = "class Main {
+    function void main() {
+        let x = 1 + ;
+        return;
+    }
+}";
+
+        assert_eq!(
+            parse_error(input),
+            "error: expected a term, found `;`
+ --> Main.jack:3:21
+  |
+3 |         let x = 1 + ;
+  |                     ^"
+        );
+    }
+
+    #[test]
+    fn expands_tabs() {
+        let input = "class Main {\n\tfunction void main() {\n\t\tdo Output.printInt(1)\n\t}\n}";
+
+        assert_eq!(
+            parse_error(input),
+            "error: expected `;`, found `}`
+ --> Main.jack:4:2
+  |
+4 |     }
+  |     ^"
+        );
+    }
+
+    #[test]
+    fn points_past_the_end() {
+        let input = "class Main {\n    field int x";
+
+        assert_eq!(
+            parse_error(input),
+            "error: expected `;`, found end of input
+ --> Main.jack:2:16
+  |
+2 |     field int x
+  |                ^"
+        );
+    }
+
+    #[test]
+    fn reports_lexer_errors() {
+        let input = "class Main {\n    field int x;\n    static int y # 1;\n}";
+        let error = tokenize(input).find_map(Result::err).unwrap();
+
+        assert_eq!(
+            error.render("Main.jack", input),
+            "error: unknown token `#`
+ --> Main.jack:3:18
+  |
+3 |     static int y # 1;
+  |                  ^"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 pub mod compilation;
 pub mod compiler;
+pub mod diagnostic;
 pub mod parse;
 pub mod tokenize;
 
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::tokenize::{Span, Spanned};
+
 #[derive(Debug)]
 pub struct Ast {
     pub class: Class,
@@ -42,14 +44,14 @@ pub struct Subroutine {
     pub body: SubroutineBody,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubroutineKind {
     Constructor,
     Function,
     Method,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ReturnType {
     Void,
     Type(Type),
@@ -76,7 +78,7 @@ pub struct SubroutineVar {
 #[derive(Debug)]
 pub enum Statement {
     Let {
-        var: String,
+        var: Spanned<String>,
         accessor: Option<Expression>,
         expression: Expression,
     },
@@ -94,7 +96,11 @@ pub enum Statement {
     },
     Return {
         value: Option<Expression>,
+        /// The span of the `return` keyword.
+        span: Span,
     },
+    /// A statement that failed to parse.
+    Error,
 }
 
 #[derive(Debug)]
@@ -104,9 +110,9 @@ pub struct Expression(pub Term, pub Vec<(Op, Term)>);
 pub enum Term {
     Int(u16),
     Str(String),
-    Keyword(Keyword),
-    Var(String),
-    Index(String, Box<Expression>),
+    Keyword(Spanned<Keyword>),
+    Var(Spanned<String>),
+    Index(Spanned<String>, Box<Expression>),
     Call(SubroutineCall),
     Paren(Box<Expression>),
     Unary(UnaryOp, Box<Term>),
@@ -129,12 +135,12 @@ pub enum Keyword {
 #[derive(Debug)]
 pub enum SubroutineCall {
     Function {
-        name: String,
+        name: Spanned<String>,
         expressions: Vec<Expression>,
     },
     Method {
-        receiver: String,
-        name: String,
+        receiver: Spanned<String>,
+        name: Spanned<String>,
         expressions: Vec<Expression>,
     },
 }
@@ -6,90 +6,154 @@ use SymbolKind::*;
 use Token::*;
 
 impl Stream {
-    pub fn parse_class(&mut self) -> Result<ast::Class> {
-        self.token(Keyword(Class))?;
+    /// Parses a class, recording errors instead of stopping at them, so the class
+    /// holds everything that could be parsed.
+    pub fn parse_class(&mut self) -> ast::Class {
+        let name = match self.parse_class_header() {
+            Ok(name) => name,
+            Err(err) => {
+                self.errors.push(err);
+
+                if self.current().is_none() {
+                    return ast::Class {
+                        name: String::new(),
+                        vars: vec![],
+                        subroutines: vec![],
+                    };
+                }
 
-        let name = self.identifier()?;
+                // skip to the class body
+                while self.next_token(|t| {
+                    !m!(
+                        t,
+                        Symbol(OpenBrace)
+                            | Keyword(Field | Static | Constructor | Function | Method)
+                    )
+                }) {
+                    self.advance().unwrap();
+                }
 
-        self.token(Symbol(OpenBrace))?;
-        let vars = self.parse_class_vars()?;
-        let subroutines = self.parse_subroutines()?;
-        self.token(Symbol(CloseBrace))?;
+                let _ = self.eat_if(|t| t == Symbol(OpenBrace));
+                String::new()
+            }
+        };
+
+        let mut vars = vec![];
+        let mut subroutines = vec![];
+
+        loop {
+            vars.extend(self.parse_class_vars());
+            subroutines.extend(self.parse_subroutines());
+
+            if m!(self.current(), None | Some(Symbol(CloseBrace))) {
+                break;
+            }
 
-        if self.current().is_some() {
-            return Err(self.unexpected_token("None"));
+            // skip the stray token, which may start a statement `recover` stops at
+            let err = self.unexpected_token("a class variable, a subroutine or `}`");
+            self.advance().unwrap();
+            self.recover(err);
         }
 
-        Ok(ast::Class {
+        if let Err(err) = self.token(Symbol(CloseBrace)) {
+            self.errors.push(err);
+        } else if self.current().is_some() {
+            let err = self.unexpected_token("end of input");
+            self.errors.push(err);
+        }
+
+        ast::Class {
             name,
             vars,
             subroutines,
-        })
+        }
     }
 
-    fn parse_class_vars(&mut self) -> Result<Vec<ast::ClassVar>> {
+    fn parse_class_header(&mut self) -> Result<String> {
+        self.token(Keyword(Class))?;
+        let name = self.identifier()?;
+        self.token(Symbol(OpenBrace))?;
+
+        Ok(name)
+    }
+
+    fn parse_class_vars(&mut self) -> Vec<ast::ClassVar> {
         let mut vars = vec![];
 
         while self.next_token(|t| m!(t, Keyword(Field | Static))) {
-            let kind = self.class_var_kind()?;
-            let r#type = self.r#type()?;
-            let name = self.identifier()?;
-            let mut names = vec![name];
-
-            while self.next_token(|t| m!(t, Symbol(Comma))) {
-                self.advance().unwrap();
-                let name = self.identifier()?;
-                names.push(name);
+            match self.parse_class_var() {
+                Ok(var) => vars.push(var),
+                Err(err) => self.recover(err),
             }
+        }
 
-            vars.push(ast::ClassVar {
-                kind,
-                r#type,
-                names,
-            });
+        vars
+    }
+
+    fn parse_class_var(&mut self) -> Result<ast::ClassVar> {
+        let kind = self.class_var_kind()?;
+        let r#type = self.r#type()?;
+        let name = self.identifier()?;
+        let mut names = vec![name];
 
-            self.token(Symbol(Semi))?;
+        while self.next_token(|t| m!(t, Symbol(Comma))) {
+            self.advance().unwrap();
+            let name = self.identifier()?;
+            names.push(name);
         }
 
-        Ok(vars)
+        self.token(Symbol(Semi))?;
+
+        Ok(ast::ClassVar {
+            kind,
+            r#type,
+            names,
+        })
     }
 
-    fn parse_subroutines(&mut self) -> Result<Vec<ast::Subroutine>> {
+    fn parse_subroutines(&mut self) -> Vec<ast::Subroutine> {
         let mut subroutines = vec![];
 
         while self.next_token(|t| m!(t, Keyword(Constructor | Function | Method))) {
-            let kind = self.subroutine_kind()?;
+            match self.parse_subroutine() {
+                Ok(subroutine) => subroutines.push(subroutine),
+                Err(err) => self.recover(err),
+            }
+        }
 
-            let r#type = if self.next_token(|t| t == Keyword(Void)) {
-                self.advance().unwrap();
-                ast::ReturnType::Void
-            } else {
-                ast::ReturnType::Type(self.r#type()?)
-            };
+        subroutines
+    }
 
-            let name = self.identifier()?;
-            self.token(Symbol(OpenParen))?;
-            let parameters = self.parse_parameter_list()?;
-            self.token(Symbol(CloseParen))?;
-            let body = self.parse_subroutine_body()?;
+    fn parse_subroutine(&mut self) -> Result<ast::Subroutine> {
+        let kind = self.subroutine_kind()?;
 
-            subroutines.push(ast::Subroutine {
-                kind,
-                name,
-                r#type,
-                parameters,
-                body,
-            });
-        }
+        let r#type = if self.next_token(|t| t == Keyword(Void)) {
+            self.advance().unwrap();
+            ast::ReturnType::Void
+        } else {
+            ast::ReturnType::Type(self.r#type()?)
+        };
 
-        Ok(subroutines)
+        let name = self.identifier()?;
+        self.token(Symbol(OpenParen))?;
+        let parameters = self.parse_parameter_list()?;
+        self.token(Symbol(CloseParen))?;
+        let body = self.parse_subroutine_body()?;
+
+        Ok(ast::Subroutine {
+            kind,
+            name,
+            r#type,
+            parameters,
+            body,
+        })
     }
 
     fn class_var_kind(&mut self) -> Result<ast::ClassVarKind> {
         let k = match self.current() {
             Some(Keyword(Field)) => ast::ClassVarKind::Field,
             Some(Keyword(Static)) => ast::ClassVarKind::Static,
-            _ => return Err(self.unexpected_token("`field` | `static`")),
+            _ => return Err(self.unexpected_token("`field` or `static`")),
         };
 
         self.advance().unwrap();
@@ -102,7 +166,7 @@ impl Stream {
             Some(Keyword(Constructor)) => ast::SubroutineKind::Constructor,
             Some(Keyword(Function)) => ast::SubroutineKind::Function,
             Some(Keyword(Method)) => ast::SubroutineKind::Method,
-            _ => return Err(self.unexpected_token("`constructor` | `function` | `method`")),
+            _ => return Err(self.unexpected_token("`constructor`, `function` or `method`")),
         };
 
         self.advance().unwrap();
@@ -136,51 +200,72 @@ impl Stream {
         let mut vars = vec![];
 
         while self.next_token(|t| t == Keyword(Var)) {
-            self.token(Keyword(Var))?;
-
-            let r#type = self.r#type()?;
-            let name = self.identifier()?;
-            let mut names = vec![name];
-
-            while self.next_token(|t| m!(t, Symbol(Comma))) {
-                self.token(Symbol(Comma))?;
-                let name = self.identifier()?;
-                names.push(name);
+            match self.parse_var_dec() {
+                Ok(var) => vars.push(var),
+                Err(err) => self.recover(err),
             }
-
-            vars.push(ast::SubroutineVar { r#type, names });
-            self.token(Symbol(Semi))?;
         }
 
-        let statements = self.parse_statements()?;
+        let statements = self.parse_statements();
         self.token(Symbol(CloseBrace))?;
 
         Ok(ast::SubroutineBody { vars, statements })
     }
 
-    fn parse_statements(&mut self) -> Result<Vec<ast::Statement>> {
+    fn parse_var_dec(&mut self) -> Result<ast::SubroutineVar> {
+        self.token(Keyword(Var))?;
+
+        let r#type = self.r#type()?;
+        let name = self.identifier()?;
+        let mut names = vec![name];
+
+        while self.next_token(|t| m!(t, Symbol(Comma))) {
+            self.token(Symbol(Comma))?;
+            let name = self.identifier()?;
+            names.push(name);
+        }
+
+        self.token(Symbol(Semi))?;
+
+        Ok(ast::SubroutineVar { r#type, names })
+    }
+
+    /// Parses statements up to the `}` that closes their block. A statement that
+    /// fails to parse is recorded as an error and left in as `Statement::Error`.
+    fn parse_statements(&mut self) -> Vec<ast::Statement> {
         let mut statements = vec![];
 
-        while self.next_token(|t| m!(t, Keyword(Let | If | While | Do | Return))) {
+        loop {
             let s = match self.current() {
+                None | Some(Symbol(CloseBrace) | Keyword(Constructor | Function | Method)) => break,
                 Some(Keyword(Let)) => self.parse_let(),
                 Some(Keyword(If)) => self.parse_if(),
                 Some(Keyword(While)) => self.parse_while(),
                 Some(Keyword(Return)) => self.parse_return(),
                 Some(Keyword(Do)) => self.parse_do(),
-                _ => unreachable!(),
+                Some(_) => {
+                    let err = self.unexpected_token("a statement");
+                    self.advance().unwrap();
+                    Err(err)
+                }
             };
 
-            statements.push(s?);
+            match s {
+                Ok(s) => statements.push(s),
+                Err(err) => {
+                    self.recover(err);
+                    statements.push(ast::Statement::Error);
+                }
+            }
         }
 
-        Ok(statements)
+        statements
     }
 
     fn parse_let(&mut self) -> Result<ast::Statement> {
         self.token(Keyword(Let))?;
 
-        let var = self.identifier()?;
+        let var = self.spanned_identifier()?;
         let mut accessor = None;
 
         if self.next_token(|t| t == Symbol(OpenBracket)) {
@@ -207,14 +292,14 @@ impl Stream {
         let cond = self.parse_expression()?;
         self.token(Symbol(CloseParen))?;
         self.token(Symbol(OpenBrace))?;
-        let statements = self.parse_statements()?;
+        let statements = self.parse_statements();
         self.token(Symbol(CloseBrace))?;
 
         let r#else = {
             if self.next_token(|t| t == Keyword(Else)) {
                 self.token(Keyword(Else))?;
                 self.token(Symbol(OpenBrace))?;
-                let statements = self.parse_statements()?;
+                let statements = self.parse_statements();
                 self.token(Symbol(CloseBrace))?;
                 Some(statements)
             } else {
@@ -235,13 +320,14 @@ impl Stream {
         let cond = self.parse_expression()?;
         self.token(Symbol(CloseParen))?;
         self.token(Symbol(OpenBrace))?;
-        let body = self.parse_statements()?;
+        let body = self.parse_statements();
         self.token(Symbol(CloseBrace))?;
 
         Ok(ast::Statement::While { cond, body })
     }
 
     fn parse_return(&mut self) -> Result<ast::Statement> {
+        let span = self.span();
         self.token(Keyword(Return))?;
 
         let mut value = None;
@@ -252,13 +338,13 @@ impl Stream {
 
         self.token(Symbol(Semi))?;
 
-        Ok(ast::Statement::Return { value })
+        Ok(ast::Statement::Return { value, span })
     }
 
     fn parse_do(&mut self) -> Result<ast::Statement> {
         self.token(Keyword(Do))?;
 
-        let ident = self.identifier()?;
+        let ident = self.spanned_identifier()?;
         let subroutine_call = self.parse_subroutine_call(ident)?;
         self.token(Symbol(Semi))?;
 
@@ -278,11 +364,11 @@ impl Stream {
         Ok(ast::Expression(term, operations))
     }
 
-    fn parse_subroutine_call(&mut self, ident: String) -> Result<ast::SubroutineCall> {
+    fn parse_subroutine_call(&mut self, ident: Spanned<String>) -> Result<ast::SubroutineCall> {
         let (name, receiver) = match self.current() {
             Some(Symbol(Dot)) => {
                 self.advance().unwrap();
-                (self.identifier()?, Some(ident))
+                (self.spanned_identifier()?, Some(ident))
             }
             _ => (ident, None),
         };
@@ -317,13 +403,30 @@ impl Stream {
     }
 
     pub fn parse_term(&mut self) -> Result<ast::Term> {
+        let is_term = |t: &Token| {
+            m!(
+                t,
+                IntegerConstant(_)
+                    | StringConstant(_)
+                    | Keyword(True | False | Null | This)
+                    | Symbol(Minus | Tilde | OpenParen)
+                    | Identifier(_)
+            )
+        };
+
+        if !self.next_token(is_term) {
+            return Err(self.unexpected_token("a term"));
+        }
+
+        let span = self.span();
+
         let t = match self.advance() {
             Some(IntegerConstant(i)) => ast::Term::Int(i),
             Some(StringConstant(s)) => ast::Term::Str(s),
-            Some(Keyword(True)) => ast::Term::Keyword(ast::Keyword::True),
-            Some(Keyword(False)) => ast::Term::Keyword(ast::Keyword::False),
-            Some(Keyword(Null)) => ast::Term::Keyword(ast::Keyword::Null),
-            Some(Keyword(This)) => ast::Term::Keyword(ast::Keyword::This),
+            Some(Keyword(True)) => ast::Term::Keyword(Spanned::new(ast::Keyword::True, span)),
+            Some(Keyword(False)) => ast::Term::Keyword(Spanned::new(ast::Keyword::False, span)),
+            Some(Keyword(Null)) => ast::Term::Keyword(Spanned::new(ast::Keyword::Null, span)),
+            Some(Keyword(This)) => ast::Term::Keyword(Spanned::new(ast::Keyword::This, span)),
             Some(Symbol(Minus)) => {
                 let term = self.parse_term()?;
                 ast::Term::Unary(ast::UnaryOp::Minus, Box::new(term))
@@ -338,16 +441,18 @@ impl Stream {
                 ast::Term::Paren(Box::new(expr))
             }
             Some(Identifier(id)) => match self.current() {
-                Some(Symbol(OpenParen | Dot)) => ast::Term::Call(self.parse_subroutine_call(id)?),
+                Some(Symbol(OpenParen | Dot)) => {
+                    ast::Term::Call(self.parse_subroutine_call(Spanned::new(id, span))?)
+                }
                 Some(Symbol(OpenBracket)) => {
                     self.advance().unwrap();
                     let expr = self.parse_expression()?;
                     self.token(Symbol(CloseBracket))?;
-                    ast::Term::Index(id, Box::new(expr))
+                    ast::Term::Index(Spanned::new(id, span), Box::new(expr))
                 }
-                _ => ast::Term::Var(id),
+                _ => ast::Term::Var(Spanned::new(id, span)),
             },
-            _ => return Err(self.unexpected_token("`term`")),
+            _ => unreachable!(),
         };
 
         Ok(t)
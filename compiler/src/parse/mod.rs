@@ -1,4 +1,4 @@
-use std::{error, fmt, result};
+use std::result;
 
 use crate::tokenize::*;
 use stream::Stream;
@@ -7,22 +7,181 @@ pub mod ast;
 mod class;
 mod stream;
 
+pub use crate::diagnostic::Diagnostic as ParseError;
+
 type Result<T> = result::Result<T, ParseError>;
 
-pub fn parse(tokens: Vec<Token>) -> Result<ast::Ast> {
+/// Parses a class, failing with the first syntax error in it.
+pub fn parse(tokens: Vec<Spanned<Token>>) -> Result<ast::Ast> {
+    let (ast, errors) = parse_recovering(tokens);
+
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(ast),
+    }
+}
+
+/// Parses a class, carrying on past syntax errors to find them all. The tree holds
+/// what could be parsed, with `Statement::Error` for statements that couldn't.
+pub fn parse_recovering(tokens: Vec<Spanned<Token>>) -> (ast::Ast, Vec<ParseError>) {
     let mut stream = Stream::new(tokens);
-    let class = stream.parse_class()?;
+    let class = stream.parse_class();
 
-    Ok(ast::Ast { class })
+    (ast::Ast { class }, stream.errors)
 }
 
-#[derive(Debug)]
-pub struct ParseError(String);
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "parse error: {}", self.0)
+    fn parse_str(input: &str) -> (ast::Ast, Vec<String>) {
+        let tokens = tokenize(input)
+            .collect::<result::Result<Vec<_>, _>>()
+            .unwrap();
+        let (ast, errors) = parse_recovering(tokens);
+
+        (ast, errors.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let input = "class Main {
+    field int x
+    static boolean y;
+
+    function void main() {
+        var int a, ;
+        let a = 1 + ;
+        if (a = ) { let a = 2; }
+        do Output.printInt(a)
+        let a = a + 1;
+        x = 5;
+        return;
+    }
+
+    method int get( {
+        return x;
+    }
+
+    function int twice(int n) {
+        return n * 2;
+    }
+}";
+
+        let (ast, errors) = parse_str(input);
+
+        assert_eq!(
+            errors,
+            [
+                "3:5: expected `;`, found `static`",
+                "6:20: expected an identifier, found `;`",
+                "7:21: expected a term, found `;`",
+                "8:17: expected a term, found `)`",
+                "10:9: expected `;`, found `let`",
+                "11:9: expected a statement, found identifier `x`",
+                "15:21: expected `)`, found `{`",
+            ]
+        );
+
+        let class = ast.class;
+        assert_eq!(class.name, "Main");
+        assert_eq!(class.vars.len(), 1);
+
+        let names: Vec<_> = class.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["main", "twice"]);
+
+        let statements = &class.subroutines[0].body.statements;
+        assert!(matches!(
+            statements[..],
+            [
+                ast::Statement::Error,
+                ast::Statement::Error,
+                ast::Statement::Error,
+                ast::Statement::Let { .. },
+                ast::Statement::Error,
+                ast::Statement::Return { .. },
+            ]
+        ));
     }
-}
 
-impl error::Error for ParseError {}
+    #[test]
+    fn recovers_between_declarations() {
+        let input = "class Main {
+    field int x;
+    let x = 1;
+
+    function void main() {
+        return;
+    }
+
+    int y;
+
+    method int get() {
+        return x;
+    }
+
+    function void broken( {
+        return;
+    }
+}";
+
+        let (ast, errors) = parse_str(input);
+
+        assert_eq!(
+            errors,
+            [
+                "3:5: expected a class variable, a subroutine or `}`, found `let`",
+                "9:5: expected a class variable, a subroutine or `}`, found `int`",
+                "15:27: expected `)`, found `{`",
+            ]
+        );
+
+        let names: Vec<_> = ast
+            .class
+            .subroutines
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, ["main", "get"]);
+        assert_eq!(ast.class.vars.len(), 1);
+
+        let (_, errors) = parse_str("");
+        assert_eq!(errors, ["1:1: expected `class`, found end of input"]);
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let input = "class Main {
+    function void main() {
+        while (true) {
+            let x = ;
+            do f();
+        }
+        return;
+    }
+";
+
+        let (ast, errors) = parse_str(input);
+
+        assert_eq!(
+            errors,
+            [
+                "4:21: expected a term, found `;`",
+                "8:6: expected `}`, found end of input",
+            ]
+        );
+
+        let statements = &ast.class.subroutines[0].body.statements;
+        let ast::Statement::While { body, .. } = &statements[0] else {
+            panic!("expected a while statement, found {:?}", statements[0]);
+        };
+
+        assert!(matches!(
+            body[..],
+            [ast::Statement::Error, ast::Statement::Do { .. }]
+        ));
+        assert!(matches!(statements[1], ast::Statement::Return { .. }));
+    }
+}
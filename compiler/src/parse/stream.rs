@@ -1,33 +1,41 @@
 use std::fmt;
 use std::iter::Peekable;
 
-use crate::tokenize::{self, KeywordKind::*, SymbolKind::*, Token::*};
+use crate::tokenize::{self, KeywordKind::*, Span, Spanned, SymbolKind::*, Token::*};
 use tokenize::Token;
 
 use super::{ast, ParseError, Result};
 
-type TokenStream = Box<dyn Iterator<Item = Token>>;
+type TokenStream = Box<dyn Iterator<Item = Spanned<Token>>>;
 
 pub struct Stream {
     tokens: Peekable<TokenStream>,
+    /// The empty span after the last token.
+    end: Span,
+    /// The errors recovered from so far.
+    pub errors: Vec<ParseError>,
 }
 
 impl Stream {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Spanned<Token>>) -> Self {
+        let end = tokens.last().map_or(Span::START, |t| t.span.after());
+
         Self {
             tokens: (Box::new(tokens.into_iter()) as TokenStream).peekable(),
+            end,
+            errors: vec![],
         }
     }
 
     #[must_use]
     pub fn advance(&mut self) -> Option<Token> {
-        self.tokens.next()
+        self.tokens.next().map(|t| t.value)
     }
 
     pub fn token(&mut self, token: Token) -> Result<Token> {
         let t = self
             .eat_if(|t| t == token)
-            .ok_or_else(|| self.unexpected_token(&token))?;
+            .ok_or_else(|| self.unexpected_token(format_args!("`{token}`")))?;
 
         Ok(t)
     }
@@ -37,14 +45,19 @@ impl Stream {
     }
 
     pub fn identifier(&mut self) -> Result<String> {
+        self.spanned_identifier().map(|id| id.value)
+    }
+
+    pub fn spanned_identifier(&mut self) -> Result<Spanned<String>> {
+        let span = self.span();
         let id = match self.current() {
             Some(Token::Identifier(id)) => id.clone(),
-            _ => return Err(self.unexpected_token("`identifier`")),
+            _ => return Err(self.unexpected_token("an identifier")),
         };
 
         self.advance().unwrap();
 
-        Ok(id)
+        Ok(Spanned::new(id, span))
     }
 
     pub fn r#type(&mut self) -> Result<ast::Type> {
@@ -53,7 +66,7 @@ impl Stream {
             Some(Keyword(Char)) => ast::Type::Char,
             Some(Keyword(Boolean)) => ast::Type::Boolean,
             Some(Identifier(id)) => ast::Type::ClassName(id.clone()),
-            _ => Err(self.unexpected_token("`int` | `char` | `boolean` | className"))?,
+            _ => Err(self.unexpected_token("`int`, `char`, `boolean` or a class name"))?,
         };
 
         self.advance().unwrap();
@@ -72,7 +85,7 @@ impl Stream {
             Some(Symbol(Lt)) => ast::Op::Lt,
             Some(Symbol(Gt)) => ast::Op::Gt,
             Some(Symbol(Eq)) => ast::Op::Eq,
-            _ => Err(self.unexpected_token("`+` | `-` | `*` | `/` | `&` | `|` | `<` | `>` | `=`"))?,
+            _ => Err(self.unexpected_token("an operator"))?,
         };
 
         self.advance().unwrap();
@@ -80,8 +93,36 @@ impl Stream {
         Ok(op)
     }
 
+    /// Records an error and skips the rest of the declaration or statement it is in:
+    /// up to and including the next `;`, or up to the next `}` or keyword that starts
+    /// a statement or declaration. Blocks on the way are skipped whole.
+    pub fn recover(&mut self, err: ParseError) {
+        self.errors.push(err);
+
+        let mut depth = 0;
+
+        while let Some(t) = self.current() {
+            match t {
+                Keyword(Constructor | Function | Method) => return,
+                Keyword(Field | Static | Var | Let | If | While | Do | Return) if depth == 0 => {
+                    return
+                }
+                Symbol(CloseBrace) if depth == 0 => return,
+                Symbol(Semi) if depth == 0 => {
+                    self.advance().unwrap();
+                    return;
+                }
+                Symbol(OpenBrace) => depth += 1,
+                Symbol(CloseBrace) => depth -= 1,
+                _ => {}
+            }
+
+            self.advance().unwrap();
+        }
+    }
+
     #[must_use]
-    fn eat_if(&mut self, f: impl FnOnce(&Token) -> bool) -> Option<Token> {
+    pub fn eat_if(&mut self, f: impl FnOnce(&Token) -> bool) -> Option<Token> {
         self.current()
             .is_some_and(f)
             .then(|| self.advance().unwrap())
@@ -89,13 +130,23 @@ impl Stream {
 
     #[must_use]
     pub fn current(&mut self) -> Option<&Token> {
-        self.tokens.peek()
+        self.tokens.peek().map(|t| &t.value)
+    }
+
+    /// The span of the current token, or the end of the input after the last one.
+    pub fn span(&mut self) -> Span {
+        self.tokens.peek().map_or(self.end, |t| t.span)
     }
 
     pub fn unexpected_token(&mut self, expected: impl fmt::Display) -> ParseError {
-        ParseError(format!(
-            "expected `{expected}`, found: {current:?}",
-            current = self.current()
-        ))
+        let found = match self.current() {
+            Some(Identifier(id)) => format!("identifier `{id}`"),
+            Some(IntegerConstant(i)) => format!("integer constant `{i}`"),
+            Some(StringConstant(s)) => format!("string constant \"{s}\""),
+            Some(t) => format!("`{t}`"),
+            None => "end of input".to_owned(),
+        };
+
+        ParseError::new(format!("expected {expected}, found {found}"), self.span())
     }
 }
@@ -22,7 +22,10 @@ impl Token {
 #[derive(Debug, PartialEq)]
 pub enum TokenKind {
     LineComment,
-    BlockComment,
+    /// "/* */", unterminated if the input ends before the comment does.
+    BlockComment {
+        terminated: bool,
+    },
     Whitespace,
 
     /// "ident" or "continue"
@@ -81,7 +84,11 @@ pub enum TokenKind {
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum LiteralKind {
     Int,
-    Str,
+    /// "\"abc\"", unterminated if the line or the input ends before the closing
+    /// quote.
+    Str {
+        terminated: bool,
+    },
 }
 
 pub fn tokenize(input: &str) -> impl Iterator<Item = Token> + '_ {
@@ -157,8 +164,8 @@ impl Cursor<'_> {
 
             // String literal.
             '"' => {
-                self.double_quoted_string();
-                Literal(Str)
+                let terminated = self.double_quoted_string();
+                Literal(Str { terminated })
             }
 
             _ => Unknown,
@@ -178,38 +185,32 @@ impl Cursor<'_> {
     fn block_comment(&mut self) -> TokenKind {
         self.bump();
 
-        let mut depth = 1usize;
+        // Jack comments don't nest, the first "*/" closes the comment
+        let mut terminated = false;
 
         while let Some(c) = self.bump() {
-            match c {
-                '/' if self.first() == '*' => {
-                    self.bump();
-                    depth += 1;
-                }
-                '*' if self.first() == '/' => {
-                    self.bump();
-                    depth -= 1;
-                    if depth == 0 {
-                        // This block comment is closed, so for a construction like "/* */ */"
-                        // there will be a successfully parsed block comment "/* */"
-                        // and " */" will be processed separately.
-                        break;
-                    }
-                }
-                _ => (),
+            if c == '*' && self.first() == '/' {
+                self.bump();
+                terminated = true;
+                break;
             }
         }
-        BlockComment
+
+        BlockComment { terminated }
     }
 
     fn whitespace(&mut self) -> TokenKind {
         self.eat_while(is_whitespace);
         Whitespace
     }
+    /// Eats a string up to its closing quote, returning whether there is one. A
+    /// string without one ends before the end of its line.
     fn double_quoted_string(&mut self) -> bool {
-        while let Some(c) = self.bump() {
-            if c == '"' {
-                return true;
+        while !matches!(self.first(), '\n' | '\r') {
+            match self.bump() {
+                Some('"') => return true,
+                Some(_) => (),
+                None => return false,
             }
         }
         false
@@ -1,22 +1,69 @@
 use std::error;
 
+use crate::diagnostic::Diagnostic;
+
 pub use token::*;
 
 pub mod lexer;
 pub mod token;
 
-pub fn tokenize(input: &str) -> impl Iterator<Item = Result<Token, Box<dyn error::Error>>> + '_ {
+/// Splits `input` into tokens, leaving out comments and whitespace, each with its
+/// span in `input`.
+pub fn tokenize(input: &str) -> impl Iterator<Item = Result<Spanned<Token>, Diagnostic>> + '_ {
     use lexer::TokenKind::*;
 
-    let mut pos = 0;
+    let (mut pos, mut line, mut column) = (0, 1, 1);
+
     lexer::tokenize(input)
         .map(move |t| {
-            let value = &input[pos..pos + t.len];
-            pos += t.len;
-            (t.kind, value)
+            let span = Span {
+                start: pos,
+                end: pos + t.len,
+                line,
+                column,
+            };
+            let value = &input[span.start..span.end];
+            pos = span.end;
+
+            for c in value.chars() {
+                if c == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+            }
+
+            (t.kind, value, span)
+        })
+        .filter_map(move |(kind, value, span)| {
+            // unterminated strings and comments are reported at where they open
+            let opening = |len| Span {
+                end: span.start + len,
+                ..span
+            };
+
+            let token = match kind {
+                LineComment | BlockComment { terminated: true } | Whitespace => return None,
+                BlockComment { terminated: false } => {
+                    Err(Diagnostic::new("unterminated block comment", opening(2)))
+                }
+                Literal(lexer::LiteralKind::Str { terminated: false }) => {
+                    let message = if span.end == input.len() {
+                        "unterminated string constant"
+                    } else {
+                        "string constant isn't closed before the end of the line"
+                    };
+
+                    Err(Diagnostic::new(message, opening(1)))
+                }
+                _ => Token::try_from((kind, value))
+                    .map(|token| Spanned::new(token, span))
+                    .map_err(|err| Diagnostic::new(err.to_string(), span)),
+            };
+
+            Some(token)
         })
-        .filter(|(k, _)| !matches!(k, LineComment | BlockComment | Whitespace))
-        .map(Token::try_from)
 }
 
 impl TryFrom<(lexer::TokenKind, &str)> for Token {
@@ -30,13 +77,17 @@ impl TryFrom<(lexer::TokenKind, &str)> for Token {
         use Token::*;
 
         let token = match kind {
-            lt::Literal(lStr) => {
+            lt::Literal(lStr { .. }) => {
                 let mut chars = value.chars();
                 chars.next();
                 chars.next_back();
                 StringConstant(chars.as_str().into())
             }
-            lt::Literal(lInt) => IntegerConstant(value.parse()?),
+            lt::Literal(lInt) => IntegerConstant(
+                value
+                    .parse()
+                    .map_err(|_| format!("integer constant `{value}` is too large"))?,
+            ),
             lt::OpenParen => Symbol(OpenParen),
             lt::CloseParen => Symbol(CloseParen),
             lt::OpenBrace => Symbol(OpenBrace),
@@ -80,9 +131,61 @@ impl TryFrom<(lexer::TokenKind, &str)> for Token {
                 "return" => Keyword(Return),
                 _ => Identifier(value.into()),
             },
+            lt::Unknown => Err(format!("unknown token `{value}`"))?,
             _ => Err(format!("cannot parse Token from: ({kind:?} '{value}')"))?,
         };
 
         Ok(token)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn errors(input: &str) -> Vec<String> {
+        tokenize(input)
+            .filter_map(Result::err)
+            .map(|err| err.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reports_unterminated_strings() {
+        let input = "do Output.printString(\"Hello);\n    return;\n    let s = \"end";
+
+        assert_eq!(
+            errors(input),
+            [
+                "1:23: string constant isn't closed before the end of the line",
+                "3:13: unterminated string constant",
+            ]
+        );
+
+        // the lines after an unterminated string are still tokenized
+        let tokens: Vec<_> = tokenize(input).filter_map(Result::ok).collect();
+        assert_eq!(tokens[5].value, Token::Keyword(KeywordKind::Return));
+        assert_eq!(tokens[5].span.line, 2);
+
+        let input = "let s = \"a\r\nb\";";
+        assert_eq!(
+            errors(input),
+            [
+                "1:9: string constant isn't closed before the end of the line",
+                "2:2: unterminated string constant",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unterminated_block_comments() {
+        let input = "/* a */ class Main {\n    /** docs */\n    /* field int x;\n}";
+
+        assert_eq!(errors(input), ["3:5: unterminated block comment"]);
+
+        let input = "/* outer /* inner */";
+        assert_eq!(errors(input), [] as [&str; 0]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, ops};
 
 impl PartialEq<Token> for &Token {
     fn eq(&self, other: &Token) -> bool {
@@ -15,6 +15,23 @@ pub enum Token {
     Identifier(String),
 }
 
+/// Where a token is in the source: its byte range, and the line and column of its
+/// first character, both starting at 1.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A value with the span of the source it was read from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum KeywordKind {
     Class,
@@ -87,16 +104,49 @@ impl Token {
     pub fn variant_matches(&self, other: &Self) -> bool {
         std::mem::discriminant(self) == std::mem::discriminant(other)
     }
+}
+
+impl Span {
+    /// The empty span at the start of the input.
+    pub const START: Span = Span {
+        start: 0,
+        end: 0,
+        line: 1,
+        column: 1,
+    };
 
+    /// The empty span right after this one, on the same line.
     #[must_use]
-    pub fn is_class(&self) -> bool {
-        match self {
-            Token::Identifier(id) => id.chars().next().is_some_and(char::is_uppercase),
-            _ => false,
+    pub fn after(&self) -> Span {
+        Span {
+            start: self.end,
+            end: self.end,
+            line: self.line,
+            column: self.column + (self.end - self.start),
         }
     }
 }
 
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
+
+impl<T> ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
 impl fmt::Display for SymbolKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use SymbolKind::*;
@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use vm_to_asm::command::VmCommand;
+use vm_to_asm::disassembler;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<_> = env::args().skip(1).map(PathBuf::from).collect();
+
+    let (input, output) = match &args[..] {
+        [input] => (input, None),
+        [input, output] => (input, Some(output)),
+        _ => {
+            println!("help: vm-disasm <input asm file> [output directory]");
+            process::exit(1);
+        }
+    };
+
+    let asm = fs::read_to_string(input)?;
+    let name = input.file_stem().and_then(|s| s.to_str()).unwrap_or("Main");
+
+    let disassembly = disassembler::disassemble(name, &asm);
+
+    match output {
+        Some(dir) => write_files(dir, &disassembly.modules)?,
+        None => {
+            if disassembly.bootstrap {
+                println!("// bootstrap: SP = 256, call Sys.init 0");
+            }
+
+            for (module, commands) in &disassembly.modules {
+                println!("// {module}.vm");
+
+                for command in commands {
+                    println!("{command}");
+                }
+            }
+        }
+    }
+
+    for lines in &disassembly.unmatched {
+        eprintln!(
+            "{}:{}-{}: no vm command translates to these lines",
+            input.display(),
+            lines.start,
+            lines.end - 1
+        );
+    }
+
+    if !disassembly.unmatched.is_empty() {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Writes every module to `dir/Module.vm`.
+fn write_files(dir: &Path, modules: &[(String, Vec<VmCommand>)]) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+
+    for (module, commands) in modules {
+        let vm: String = commands.iter().map(|c| format!("{c}\n")).collect();
+        fs::write(dir.join(format!("{module}.vm")), vm)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+use vm_to_asm::emulator::{Emulator, ARG, LCL, SP, THAT, THIS};
+use vm_to_asm::files;
+
+const MAX_STEPS: usize = 100_000_000;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let mut inputs = vec![];
+    let mut max_steps = MAX_STEPS;
+    let mut keys = None;
+    let mut checking = false;
+    let mut vm_extensions = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => {
+                max_steps = args.next().ok_or("--steps expects a number")?.parse()?;
+            }
+            "--input" => keys = Some(args.next().ok_or("--input expects a file")?),
+            "--check" => checking = true,
+            "--vm-extensions" => vm_extensions = true,
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        println!(
+            "help: vm-emulator <input vm file.. | directory> [--steps N] [--input FILE] [--check] [--vm-extensions]"
+        );
+        process::exit(1);
+    }
+
+    let mut emulator = Emulator::new();
+    emulator.set_checking(checking);
+    emulator.set_vm_extensions(vm_extensions);
+
+    for file in files::expand(&inputs)? {
+        let input = fs::read_to_string(&file)?;
+        let name = file.file_name().unwrap().to_str().unwrap();
+
+        emulator.load(name, &input)?;
+    }
+
+    if let Some(keys) = keys {
+        emulator.type_keys(&fs::read_to_string(keys)?);
+    }
+
+    emulator.bootstrap();
+    let result = emulator.run(max_steps);
+
+    if !emulator.output().is_empty() {
+        println!("{}", emulator.output());
+    }
+
+    match result {
+        Ok(steps) if emulator.is_halted() => println!("halted after {steps} steps"),
+        Ok(steps) => println!("stopped after {steps} steps"),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(1);
+        }
+    }
+
+    for (name, pointer) in [
+        ("SP", SP),
+        ("LCL", LCL),
+        ("ARG", ARG),
+        ("THIS", THIS),
+        ("THAT", THAT),
+    ] {
+        println!("{name:>4}: {}", emulator.peek(pointer));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+use vm_to_asm::{files, lint, parser};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut inputs = vec![];
+    let mut vm_extensions = false;
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--vm-extensions" => vm_extensions = true,
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        println!("help: vm-lint <input vm file.. | directory> [--vm-extensions]");
+        process::exit(1);
+    }
+
+    let mut sources = vec![];
+
+    for file in files::expand(&inputs)? {
+        let name = file.file_name().unwrap().to_str().unwrap().to_owned();
+        sources.push((name, fs::read_to_string(&file)?));
+    }
+
+    let inputs: Vec<_> = sources
+        .iter()
+        .map(|(name, source)| (name.as_str(), source.as_str()))
+        .collect();
+
+    let modules = match parser::parse_files_with(&inputs, vm_extensions) {
+        Ok(modules) => modules,
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("error: {e}");
+            }
+
+            process::exit(1);
+        }
+    };
+
+    let lints = lint::lint(&modules);
+
+    for lint in &lints {
+        println!("{lint}");
+    }
+
+    let errors = lints.iter().filter(|l| l.kind.is_error()).count();
+    let warnings = lints.len() - errors;
+
+    if !lints.is_empty() {
+        println!("{errors} error(s), {warnings} warning(s)");
+    }
+
+    if errors > 0 {
+        process::exit(1);
+    }
+
+    Ok(())
+}
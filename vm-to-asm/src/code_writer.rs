@@ -1,14 +1,61 @@
-use crate::CommandType;
+use crate::command::{Op, Segment, SourceCommand, VmCommand};
+use crate::error::{ErrorKind, Result, VmError};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::Result;
-use std::io::Write;
+use std::io::{self, Write};
+use std::ops::Range;
 
-#[derive(Debug, Default)]
-pub struct CodeWriter {
-    output_file: Option<File>,
+/// Translates VM commands to Hack assembly written to `W`, a file unless given
+/// another output with `with_output`.
+#[derive(Debug)]
+pub struct CodeWriter<W = File> {
+    output: Option<Output<W>>,
     module: Option<String>,
+    /// The function being written, which labels and return addresses are scoped to.
+    function: Option<String>,
+    line: usize,
     jump_counter: u16,
+    /// Calls written so far in the current function.
     return_counter: u16,
+    shared: bool,
+    fast_comparisons: bool,
+    no_superinstructions: bool,
+    /// Shared routines called so far, written out by `close`.
+    routines: BTreeSet<Routine>,
+    /// Set when the output is annotated with the commands it comes from.
+    source_map: Option<Vec<SourceMapEntry>>,
+    /// Functions called with a frame that doesn't save THIS and THAT.
+    slim_frames: HashSet<String>,
+}
+
+/// The ROM addresses of the code translated from a line of a .vm file.
+///
+/// Written to a source map as `start end file line`, one entry per line, with the
+/// end address excluded. A sequence translated together has a single entry, with the
+/// line of its first command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    pub rom: Range<u16>,
+    pub file: String,
+    pub line: usize,
+}
+
+/// The output, counting the instructions written to it.
+#[derive(Debug)]
+struct Output<W> {
+    out: W,
+    instructions: u16,
+    /// Whether the first character of the current line is still to come.
+    line_start: bool,
+}
+
+/// Subroutines shared by every call site in shared mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Routine {
+    Call { slim: bool },
+    Return { slim: bool },
+    Compare(Op),
 }
 
 impl CodeWriter {
@@ -16,23 +63,118 @@ impl CodeWriter {
         Self::default()
     }
 
-    pub fn set_file_name(&mut self, name: &str) {
-        let file = File::create(name).expect("set_filename: file not found");
-        self.output_file = Some(file);
+    pub fn set_file_name(&mut self, name: &str) -> Result {
+        self.set_output(File::create(name)?);
+
+        Ok(())
+    }
+}
+
+impl<W> Default for CodeWriter<W> {
+    fn default() -> Self {
+        CodeWriter {
+            output: None,
+            module: None,
+            function: None,
+            line: 0,
+            jump_counter: 0,
+            return_counter: 0,
+            shared: false,
+            fast_comparisons: false,
+            no_superinstructions: false,
+            routines: BTreeSet::new(),
+            source_map: None,
+            slim_frames: HashSet::new(),
+        }
+    }
+}
+
+impl<W: Write> CodeWriter<W> {
+    pub fn with_output(out: W) -> Self {
+        let mut code_writer = Self::default();
+        code_writer.set_output(out);
+
+        code_writer
+    }
+
+    /// Starts a new program, written to `out`.
+    pub fn set_output(&mut self, out: W) {
+        self.output = Some(Output::new(out));
         self.jump_counter = 0;
-        self.return_counter = 0;
+        self.routines.clear();
+
+        if let Some(entries) = self.source_map.as_mut() {
+            entries.clear();
+        }
+    }
+
+    /// Emits `call`, `return`, `eq`, `gt` and `lt` as jumps to subroutines written
+    /// once at the end of the program, instead of inlining them at every use.
+    pub fn set_shared_routines(&mut self, shared: bool) {
+        self.shared = shared;
+    }
+
+    /// Compares with `gt` and `lt` by subtracting the operands, which is shorter but
+    /// wrong when the subtraction overflows, e.g. `32767 gt -1` is false.
+    pub fn set_fast_comparisons(&mut self, fast: bool) {
+        self.fast_comparisons = fast;
+    }
+
+    /// Translating common sequences of commands together, see `write_commands`, is
+    /// enabled by default. Disabling it makes the output follow the commands one by one.
+    pub fn set_superinstructions(&mut self, enabled: bool) {
+        self.no_superinstructions = !enabled;
+    }
+
+    /// Comments the code of every command with the command and its `File.vm:line`,
+    /// and records the ROM addresses it takes, see `source_map`.
+    pub fn set_source_map(&mut self, enabled: bool) {
+        self.source_map = enabled.then(Vec::new);
+    }
+
+    /// The source map of the code written to the current file, in ROM order, empty
+    /// unless enabled with `set_source_map`.
+    pub fn source_map(&self) -> &[SourceMapEntry] {
+        self.source_map.as_deref().unwrap_or_default()
+    }
+
+    /// Calls `functions` and returns from them with frames that only save LCL and ARG,
+    /// which is only correct for functions that never set `pointer`, see
+    /// `frames::slim_functions`. Other functions keep the standard frame.
+    pub fn set_slim_frames(&mut self, functions: HashSet<String>) {
+        self.slim_frames = functions;
     }
 
     pub fn set_module_name(&mut self, name: &str) {
         self.module = Some(name.to_string());
+        self.function = None;
+        self.return_counter = 0;
     }
 
-    pub fn close(&mut self) {
-        self.output_file = None;
+    /// Writes the shared routines that were used, and closes the output.
+    pub fn close(&mut self) -> Result {
+        if let Some(out) = self.output.as_mut() {
+            if !self.routines.is_empty() {
+                // keeps a program that runs off its end out of the routines
+                writeln!(out, "($$END)\n    @$$END\n    0;JMP")?;
+            }
+
+            for routine in &self.routines {
+                let code = routine.code(self.fast_comparisons);
+                writeln!(out, "({})\n{code}", routine.label())?;
+            }
+
+            out.flush()?;
+        }
+
+        self.routines.clear();
+        self.output = None;
+
+        Ok(())
     }
 
-    pub fn write_init(&mut self) -> Result<()> {
-        let out = self.output_file.as_mut().expect("output_file is set");
+    pub fn write_init(&mut self) -> Result {
+        let out = self.output.as_mut().expect("output is set");
 
         writeln!(
             out,
@@ -45,18 +187,149 @@ impl CodeWriter {
         self.write_call("Sys.init", 0)
     }
 
-    pub fn write_arithmetic(&mut self, command: &str) -> Result<()> {
-        let out = self.output_file.as_mut().expect("output_file is set");
+    /// Writes the assembly code of parsed commands, translating these sequences together
+    /// into shorter code:
+    ///
+    /// - `push constant n; add` (also `sub`, `and` and `or`) applies the operation to
+    ///   the top of the stack in place
+    /// - `push x; push constant 1; add; pop x` (or `sub`) increments `x` in place, for
+    ///   `static`, `temp` and `pointer` the constant can be any number
+    /// - a comparison followed by `if-goto` jumps without pushing the boolean, unless
+    ///   it would be longer than calling the shared routine
+    pub fn write_commands(&mut self, commands: &[SourceCommand]) -> Result {
+        let mut rest = commands;
+
+        while let Some(command) = rest.first() {
+            let fused = match self.no_superinstructions {
+                true => 0,
+                false => self.write_superinstruction(rest)?,
+            };
+
+            if fused == 0 {
+                self.write_command(command)?;
+                rest = &rest[1..];
+            } else {
+                rest = &rest[fused..];
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a sequence at the start of `commands`, returning how many commands it
+    /// covers, or 0 if none matches.
+    fn write_superinstruction(&mut self, commands: &[SourceCommand]) -> Result<usize> {
+        use VmCommand::*;
+
+        let window = &commands[..commands.len().min(4)];
+
+        // invalid commands are reported one at a time
+        if window.iter().any(|c| c.command.check().is_err()) {
+            return Ok(0);
+        }
+
+        self.line = window[0].line;
+        let window: Vec<_> = window.iter().map(|c| &c.command).collect();
+
+        match window[..] {
+            [&Push(segment, index), &Push(Segment::Constant, n), &Arithmetic(op @ (Op::Add | Op::Sub)), &Pop(s, i)]
+                if segment == s
+                    && index == i
+                    && segment != Segment::Constant
+                    && (n == 1
+                        || matches!(
+                            segment,
+                            Segment::Static | Segment::Temp | Segment::Pointer
+                        )) =>
+            {
+                self.write_block(&commands[..4], |w| w.write_in_place(segment, index, op, n))?;
+                Ok(4)
+            }
+            [&Push(Segment::Constant, n), &Arithmetic(op @ (Op::Add | Op::Sub | Op::And | Op::Or)), ..] =>
+            {
+                self.write_block(&commands[..2], |w| w.write_constant_operation(op, n))?;
+                Ok(2)
+            }
+            [&Arithmetic(op), IfGoto(label), ..]
+                if op.is_comparison()
+                    && !(self.shared
+                        && !self.fast_comparisons
+                        && !matches!(op, Op::Eq | Op::Ne)) =>
+            {
+                self.write_block(&commands[..2], |w| w.write_comparison_branch(op, label))?;
+                Ok(2)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// Writes the assembly code of a parsed command.
+    pub fn write_command(&mut self, command: &SourceCommand) -> Result {
+        self.line = command.line;
+
+        if let Err(kind) = command.command.check() {
+            return Err(self.error(kind));
+        }
+
+        self.write_block(std::slice::from_ref(command), |w| match &command.command {
+            VmCommand::Arithmetic(op) => w.write_arithmetic(*op),
+            VmCommand::Push(segment, index) => w.write_push(*segment, *index),
+            VmCommand::Pop(segment, index) => w.write_pop(*segment, *index),
+            VmCommand::Label(label) => w.write_label(label),
+            VmCommand::Goto(label) => w.write_goto(label),
+            VmCommand::IfGoto(label) => w.write_if(label),
+            VmCommand::Function { name, n_vars } => w.write_function(name, *n_vars),
+            VmCommand::Call { name, n_args } => w.write_call(name, *n_args),
+            VmCommand::Return => w.write_return(),
+        })
+    }
+
+    /// Writes the code of `commands` with `write`, preceded by the commands and
+    /// recorded in the source map when it's enabled.
+    fn write_block(
+        &mut self,
+        commands: &[SourceCommand],
+        write: impl FnOnce(&mut Self) -> Result,
+    ) -> Result {
+        if self.source_map.is_none() {
+            return write(self);
+        }
+
+        let module = self.module.clone().expect("module is set");
+        let out = self.output.as_mut().expect("output is set");
+
+        for command in commands {
+            writeln!(out, "// {module}:{}: {}", command.line, command.command)?;
+        }
+
+        let start = out.instructions;
+        write(self)?;
+        let end = self.output.as_ref().expect("output is set").instructions;
+
+        if end > start {
+            let entries = self.source_map.as_mut().expect("source map is enabled");
+            entries.push(SourceMapEntry {
+                rom: start..end,
+                file: module,
+                line: commands[0].line,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn write_arithmetic(&mut self, command: Op) -> Result {
+        let out = self.output.as_mut().expect("output is set");
 
         let module = self.module.as_ref().expect("module is set");
 
         match command {
-            "add" | "sub" | "and" | "or" => {
+            Op::Add | Op::Sub | Op::And | Op::Or => {
                 let op = match command {
-                    "add" => "+",
-                    "sub" => "-",
-                    "and" => "&",
-                    "or" => "|",
+                    Op::Add => "+",
+                    Op::Sub => "-",
+                    Op::And => "&",
+                    Op::Or => "|",
                     _ => unreachable!(),
                 };
 
@@ -67,13 +340,13 @@ impl CodeWriter {
     D=M
     A=A-1
     M=M{op}D // {command}"
-                )
+                )?;
             }
 
-            "neg" | "not" => {
+            Op::Neg | Op::Not => {
                 let op = match command {
-                    "neg" => "-",
-                    "not" => "!",
+                    Op::Neg => "-",
+                    Op::Not => "!",
                     _ => unreachable!(),
                 };
 
@@ -82,112 +355,192 @@ impl CodeWriter {
                     "    @SP
     A=M-1
     M={op}M // {command}"
-                )
+                )?;
             }
 
-            "eq" | "gt" | "lt" => {
-                let jump = match command {
-                    "eq" => "JEQ",
-                    "gt" => "JGT",
-                    "lt" => "JLT",
-                    _ => unreachable!(),
-                };
+            Op::Mul | Op::Div | Op::Shl | Op::Shr => {
+                let id = self.jump_counter;
+                self.jump_counter += 1;
+
+                let code = loop_operation(command, |name| format!("{module}${name}{id}"));
+
+                writeln!(out, "{code} // {command}")?;
+            }
+
+            _ if self.shared => {
+                let routine = Routine::Compare(command);
 
                 let id = self.jump_counter;
                 self.jump_counter += 1;
 
                 writeln!(
                     out,
-                    "    @SP
-    AM=M-1
-    D=M
-    A=A-1
-    D=M-D
-    @{module}$if_true{id}
-    D;{jump}
-    D=0
-    @{module}$if_end{id}
-    0;JMP
-({module}$if_true{id})
-    D=-1
-({module}$if_end{id})
-    @SP
+                    "    @{module}$cmp_ret{id}
+    D=A
+    @{label}
+    0;JMP // {command}
+({module}$cmp_ret{id})",
+                    label = routine.label()
+                )?;
+
+                self.routines.insert(routine);
+            }
+
+            _ => {
+                let id = self.jump_counter;
+                self.jump_counter += 1;
+
+                let code = comparison(command, self.fast_comparisons, |name| {
+                    format!("{module}${name}{id}")
+                });
+
+                writeln!(out, "{code} // {command}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_constant_operation(&mut self, op: Op, n: u16) -> Result {
+        let out = self.output.as_mut().expect("output is set");
+
+        let (load, operand) = match n {
+            1 if matches!(op, Op::Add | Op::Sub) => ("", "1"),
+            _ => (&*format!("    @{n}\n    D=A\n"), "D"),
+        };
+
+        let computation = match op {
+            Op::Add => format!("M+{operand}"),
+            Op::Sub => format!("M-{operand}"),
+            Op::And => "D&M".to_owned(),
+            Op::Or => "D|M".to_owned(),
+            _ => unreachable!(),
+        };
+
+        writeln!(
+            out,
+            "{load}    @SP
     A=M-1
-    M=D // {command}"
-                )
+    M={computation} // push constant {n}; {op}"
+        )?;
+
+        Ok(())
+    }
+
+    fn write_in_place(&mut self, segment: Segment, index: u16, op: Op, n: u16) -> Result {
+        let static_symbol = self.static_symbol(index);
+        let out = self.output.as_mut().expect("output is set");
+
+        let sign = if op == Op::Add { "+" } else { "-" };
+
+        let address = match segment {
+            Segment::Argument | Segment::Local | Segment::This | Segment::That => {
+                let symbol = get_segment_symbol(segment);
+
+                match index {
+                    0 => format!("    @{symbol}\n    A=M"),
+                    _ => format!("    @{symbol}\n    D=M\n    @{index}\n    A=D+A"),
+                }
             }
+            Segment::Pointer | Segment::Temp => {
+                format!("    @{}", get_fixed_symbol(segment, index))
+            }
+            Segment::Static => format!("    @{static_symbol}"),
+            Segment::Constant => unreachable!("constant can't be updated"),
+        };
 
-            c => unreachable!("invavalid command{c}"),
+        if n == 1 {
+            writeln!(
+                out,
+                "{address}\n    M=M{sign}1 // {segment} {index} {sign}= 1"
+            )?;
+        } else {
+            writeln!(
+                out,
+                "    @{n}\n    D=A\n{address}\n    M=M{sign}D // {segment} {index} {sign}= {n}"
+            )?;
         }
+
+        Ok(())
     }
 
-    pub fn write_pushpop(&mut self, command: CommandType, segment: &str, index: u16) -> Result<()> {
-        let out = self.output_file.as_mut().expect("file is set");
-        let filename = self.module.as_ref().expect("module is set");
+    fn write_comparison_branch(&mut self, op: Op, label: &str) -> Result {
+        let target = self.local_label(label);
+        let out = self.output.as_mut().expect("output is set");
+        let module = self.module.as_ref().expect("module is set");
 
-        let basename = filename
-            .strip_suffix(".vm")
-            .expect("file is a list of vm instructions");
+        let id = self.jump_counter;
+        self.jump_counter += 1;
 
-        let static_symbol = format!("{basename}.{index}");
+        let code = comparison_branch(op, self.fast_comparisons, &target, |name| {
+            format!("{module}${name}{id}")
+        });
 
-        use CommandType::*;
-        match command {
-            Push => match segment {
-                "constant" => {
-                    writeln!(
-                        out,
-                        "    @{index}
+        writeln!(out, "{code} // {op}; if-goto {label}")?;
+
+        Ok(())
+    }
+
+    pub fn write_push(&mut self, segment: Segment, index: u16) -> Result {
+        let static_symbol = self.static_symbol(index);
+        let out = self.output.as_mut().expect("output is set");
+
+        match segment {
+            Segment::Constant => {
+                writeln!(
+                    out,
+                    "    @{index}
     D=A
     {PUSH_REGD}       // push {segment} {index}"
-                    )
-                }
-                "argument" | "local" | "this" | "that" => {
-                    let symbol = get_segment_symbol(segment);
+                )?;
+            }
+            Segment::Argument | Segment::Local | Segment::This | Segment::That => {
+                let symbol = get_segment_symbol(segment);
 
-                    writeln!(
-                        out,
-                        "    @{symbol}
+                writeln!(
+                    out,
+                    "    @{symbol}
     D=M
     @{index}
     A=A+D
     D=M
     {PUSH_REGD}             // push {segment} {index}"
-                    )
-                }
-                "pointer" | "temp" => {
-                    let index = index as usize;
-                    let symbol = match segment {
-                        "pointer" => ["THIS", "THAT"][index],
-                        "temp" => ["R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12"][index],
-                        _ => unreachable!(),
-                    };
-
-                    writeln!(
-                        out,
-                        "    @{symbol}
+                )?;
+            }
+            Segment::Pointer | Segment::Temp => {
+                let symbol = get_fixed_symbol(segment, index);
+
+                writeln!(
+                    out,
+                    "    @{symbol}
     D=M
     {PUSH_REGD}"
-                    )
-                }
-                "static" => {
-                    writeln!(
-                        out,
-                        "    @{static_symbol}
+                )?;
+            }
+            Segment::Static => {
+                writeln!(
+                    out,
+                    "    @{static_symbol}
     D=M
     {PUSH_REGD}"
-                    )
-                }
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_pop(&mut self, segment: Segment, index: u16) -> Result {
+        let static_symbol = self.static_symbol(index);
+        let out = self.output.as_mut().expect("output is set");
 
-                s => panic!("invalid segment: {s}"),
-            },
-            Pop => match segment {
-                "argument" | "local" | "this" | "that" => {
-                    let symbol = get_segment_symbol(segment);
+        match segment {
+            Segment::Argument | Segment::Local | Segment::This | Segment::That => {
+                let symbol = get_segment_symbol(segment);
 
-                    writeln!(
-                        out,
-                        "    @{symbol}
+                writeln!(
+                    out,
+                    "    @{symbol}
     D=M
     @{index}
     D=D+A
@@ -199,75 +552,71 @@ impl CodeWriter {
     @R13
     A=M
     M=D"
-                    )
-                }
-                "pointer" | "temp" => {
-                    let index = index as usize;
-
-                    let symbol = match segment {
-                        "pointer" => ["THIS", "THAT"][index],
-                        "temp" => ["R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12"][index],
-                        _ => unreachable!(),
-                    };
-
-                    writeln!(
-                        out,
-                        "    @SP
+                )?;
+            }
+            Segment::Pointer | Segment::Temp => {
+                let symbol = get_fixed_symbol(segment, index);
+
+                writeln!(
+                    out,
+                    "    @SP
     AM=M-1
     D=M
     @{symbol}
     M=D // pop {symbol} {index}"
-                    )
-                }
-
-                "static" => {
-                    writeln!(
-                        out,
-                        "    @SP
+                )?;
+            }
+            Segment::Static => {
+                writeln!(
+                    out,
+                    "    @SP
     AM=M-1
     D=M
     @{static_symbol}
     M=D"
-                    )
-                }
-
-                _ => unreachable!("invalid segment: {segment}"),
-            },
-
-            _ => panic!("invalid command: {command:?}"),
+                )?;
+            }
+            Segment::Constant => {
+                return Err(self.error(ErrorKind::InvalidSegment(segment.to_string())))
+            }
         }
+
+        Ok(())
     }
 
-    pub fn write_label(&mut self, label: &str) -> Result<()> {
+    pub fn write_label(&mut self, label: &str) -> Result {
         let label = self.local_label(label);
-        let out = self.output_file.as_mut().expect("file is set");
+        let out = self.output.as_mut().expect("output is set");
 
-        writeln!(out, "({label})")
+        Ok(writeln!(out, "({label})")?)
     }
 
-    pub fn write_if(&mut self, label: &str) -> Result<()> {
+    pub fn write_if(&mut self, label: &str) -> Result {
         let label = self.local_label(label);
-        let out = self.output_file.as_mut().expect("file is set");
+        let out = self.output.as_mut().expect("output is set");
 
-        writeln!(
+        Ok(writeln!(
             out,
             "    @SP
     AM=M-1
     D=M
     @{label}
     D;JNE"
-        )
+        )?)
     }
 
-    pub fn write_goto(&mut self, label: &str) -> Result<()> {
+    pub fn write_goto(&mut self, label: &str) -> Result {
         let label = self.local_label(label);
-        let out = self.output_file.as_mut().expect("file is set");
+        let out = self.output.as_mut().expect("output is set");
 
-        writeln!(out, "    @{label}\n    0;JMP")
+        Ok(writeln!(out, "    @{label}\n    0;JMP")?)
     }
 
-    pub fn write_function(&mut self, name: &str, n_vars: u16) -> Result<()> {
-        let out = self.output_file.as_mut().expect("file is set");
+    pub fn write_function(&mut self, name: &str, n_vars: u16) -> Result {
+        self.function = Some(name.to_owned());
+        self.return_counter = 0;
+
+        let out = self.output.as_mut().expect("output is set");
 
         writeln!(out, "({name})      // function {name} {n_vars}")?;
 
@@ -282,42 +631,70 @@ impl CodeWriter {
         Ok(())
     }
 
-    pub fn write_call(&mut self, name: &str, n_args: u16) -> Result<()> {
-        let out = self.output_file.as_mut().expect("file is set");
-
+    pub fn write_call(&mut self, name: &str, n_args: u16) -> Result {
         self.return_counter += 1;
 
-        let return_address_label = format!("{name}$ret.{counter}", counter = self.return_counter);
+        let return_address_label = format!(
+            "{scope}$ret.{counter}",
+            scope = self.scope(),
+            counter = self.return_counter
+        );
 
-        writeln!(
-            out,
-            "    @{return_address_label}
+        let slim = self.slim_frames.contains(name);
+        let out = self.output.as_mut().expect("output is set");
+
+        if self.shared {
+            let routine = Routine::Call { slim };
+            self.routines.insert(routine);
+
+            return Ok(writeln!(
+                out,
+                "    @{n_args}
     D=A
-    {PUSH_REGD}       // push retAddrLabel
-    @LCL
-    A=M
+    @R13
+    M=D               // R13 = nArgs
+    @{name}
     D=A
-    {PUSH_REGD}       // push LCL
-    @ARG
-    A=M
+    @R14
+    M=D               // R14 = function
+    @{return_address_label}
     D=A
-    {PUSH_REGD}       // push ARG
-    @THIS
+    @{call}
+    0;JMP             // call {name} {n_args}
+({return_address_label})",
+                call = routine.label()
+            )?);
+        }
+
+        let saved = saved_registers(slim);
+        let frame = saved.len() + 1;
+
+        let pushes: String = saved
+            .iter()
+            .map(|register| {
+                format!(
+                    "
+    @{register}
     A=M
     D=A
-    {PUSH_REGD}       // push THIS
-    @THAT
-    A=M
+    {PUSH_REGD}       // push {register}"
+                )
+            })
+            .collect();
+
+        Ok(writeln!(
+            out,
+            "    @{return_address_label}
     D=A
-    {PUSH_REGD}       // push THAT
+    {PUSH_REGD}       // push retAddrLabel{pushes}
     @SP
     D=M
-    @5
+    @{frame}
     D=D-A
     @{n_args}
     D=D-A
     @ARG
-    M=D               // ARG = SP - 5 - nArgs
+    M=D               // ARG = SP - {frame} - nArgs
     @SP
     D=M
     @LCL
@@ -325,87 +702,122 @@ impl CodeWriter {
     @{name}
     0;JMP
 ({return_address_label})"
-        )
+        )?)
     }
 
-    pub fn write_return(&mut self) -> Result<()> {
-        let out = self.output_file.as_mut().expect("file is set");
+    pub fn write_return(&mut self) -> Result {
+        let slim = matches!(&self.function, Some(f) if self.slim_frames.contains(f));
+        let out = self.output.as_mut().expect("output is set");
 
-        writeln!(
-            out,
-            "    @LCL
-    D=M
-    @R13
-    M=D // endFrame = LCL
-    @R13
-    D=M
-    @5
-    D=D-A
-    A=D
-    D=M
-    @R14
-    M=D // retAddr = *(endFrame - 5)
-    @SP
-    AM=M-1
-    D=M
-    @ARG
-    A=M
-    M=D // *ARG = pop()
-    D=A+1
-    @SP
-    M=D // SP = ARG + 1
-    @R13
-    D=M
-    @1
-    D=D-A
-    A=D
-    D=M
-    @THAT
-    M=D // THAT = *(endFrame - 1)
-    @R13
-    D=M
-    @2
-    D=D-A
-    A=D
-    D=M
-    @THIS
-    M=D // THIS = *(endFrame - 2)
-    @R13
-    D=M
-    @3
-    D=D-A
-    A=D
-    D=M
-    @ARG
-    M=D // ARG = *(endFrame - 3)
-    @R13
-    D=M
-    @4
-    D=D-A
-    A=D
-    D=M
-    @LCL
-    M=D // LCL = *(endFrame - 4)
-    @R14
-    A=M
-    0;JMP // goto retAddr"
-        )
-    }
+        if self.shared {
+            let routine = Routine::Return { slim };
+            self.routines.insert(routine);
 
-    fn local_label(&self, label: &str) -> String {
-        let module = self.module.as_ref().expect("module is set");
+            writeln!(out, "    @{}\n    0;JMP // return", routine.label())?;
+        } else {
+            writeln!(out, "{}", return_code(slim))?;
+        }
+
+        Ok(())
+    }
+
+    /// `function$label` as in the book.
+    fn local_label(&self, label: &str) -> String {
+        format!("{}${label}", self.scope())
+    }
+
+    /// The current function, or the module for commands that come before the first
+    /// function of a file, like the emulator. The bootstrap code comes before any file.
+    fn scope(&self) -> &str {
+        match (&self.function, &self.module) {
+            (Some(function), _) => function,
+            (None, Some(module)) => module.strip_suffix(".vm").unwrap_or(module),
+            (None, None) => "$bootstrap",
+        }
+    }
+
+    fn static_symbol(&self, index: u16) -> String {
+        let filename = self.module.as_ref().expect("module is set");
+
+        let basename = filename
+            .strip_suffix(".vm")
+            .expect("file is a list of vm instructions");
+
+        format!("{basename}.{index}")
+    }
+
+    fn error(&self, kind: ErrorKind) -> VmError {
+        VmError::Command {
+            file: self.module.clone().unwrap_or_default(),
+            line: self.line,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for SourceMapEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let SourceMapEntry { rom, file, line } = self;
+
+        write!(f, "{} {} {file} {line}", rom.start, rom.end)
+    }
+}
+
+impl<W> Output<W> {
+    fn new(out: W) -> Self {
+        Output {
+            out,
+            instructions: 0,
+            line_start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
 
-        format!("{module}${label}")
+        // every line starting with anything but a label or a comment is an instruction
+        for &byte in &buf[..n] {
+            match byte {
+                b'\n' => self.line_start = true,
+                b' ' | b'\t' | b'\r' => {}
+                _ if self.line_start => {
+                    self.line_start = false;
+
+                    if byte != b'(' && byte != b'/' {
+                        self.instructions += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn get_segment_symbol(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Argument => "ARG",
+        Segment::Local => "LCL",
+        Segment::This => "THIS",
+        Segment::That => "THAT",
+        _ => unreachable!("segment {segment} has no base address"),
     }
 }
 
-fn get_segment_symbol(segment: &str) -> &'static str {
+fn get_fixed_symbol(segment: Segment, index: u16) -> &'static str {
+    let index = index as usize;
+
     match segment {
-        "argument" => "ARG",
-        "local" => "LCL",
-        "this" => "THIS",
-        "that" => "THAT",
-        _ => panic!("invalid segment name: {}", segment),
+        Segment::Pointer => ["THIS", "THAT"][index],
+        Segment::Temp => ["R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12"][index],
+        _ => unreachable!("segment {segment} is not fixed"),
     }
 }
 
@@ -414,3 +826,818 @@ const PUSH_REGD: &str = "@SP
     M=D
     @SP
     M=M+1";
+
+impl Routine {
+    fn label(self) -> String {
+        match self {
+            Routine::Call { slim: false } => "$$CALL".to_owned(),
+            Routine::Call { slim: true } => "$$SLIM_CALL".to_owned(),
+            Routine::Return { slim: false } => "$$RETURN".to_owned(),
+            Routine::Return { slim: true } => "$$SLIM_RETURN".to_owned(),
+            Routine::Compare(op) => format!("$${}", op.to_string().to_uppercase()),
+        }
+    }
+
+    /// The routine's code, which returns to the address it receives in D, except
+    /// for the returns, which return to the caller of the VM function.
+    fn code(self, fast_comparisons: bool) -> String {
+        let op = match self {
+            Routine::Call { slim } => return call_routine(slim),
+            Routine::Return { slim } => return return_code(slim),
+            Routine::Compare(op) => op,
+        };
+
+        let code = comparison(op, fast_comparisons, |name| {
+            format!("{}.{name}", self.label())
+        });
+
+        format!(
+            "    @R13
+    M=D
+{code}
+    @R13
+    A=M
+    0;JMP"
+        )
+    }
+}
+
+/// Replaces the two values on top of the stack with the result of the comparison.
+fn comparison(op: Op, fast: bool, label: impl Fn(&str) -> String) -> String {
+    let end = label("end");
+    let jump = jump(op);
+
+    let (difference, exact) = difference(op, fast, &label);
+    let point_to_x = if exact { "" } else { "    @SP\n    A=M-1\n" };
+
+    format!(
+        "{difference}
+{point_to_x}    M=-1
+    @{end}
+    D;{jump}
+    @SP
+    A=M-1
+    M=0
+({end})"
+    )
+}
+
+/// Replaces the two values on top of the stack with the result of `mul`, `div`,
+/// `shl` or `shr`, computed by a loop over the bits of the operands.
+///
+/// `div` works on the operands made negative, since -32768 has no positive
+/// counterpart, and keeps the divisor doubled up to the dividend in the free stack
+/// space above SP.
+fn loop_operation(op: Op, label: impl Fn(&str) -> String) -> String {
+    let (start, end) = (label("loop"), label("end"));
+
+    match op {
+        // R13 = y, the bits left to add, R14 = x shifted, R15 = the bit
+        Op::Mul => {
+            let skip = label("skip");
+
+            format!(
+                "    @SP
+    AM=M-1
+    D=M
+    @R13
+    M=D
+    @SP
+    A=M-1
+    D=M
+    @R14
+    M=D
+    @SP
+    A=M-1
+    M=0
+    @R15
+    M=1
+({start})
+    @R15
+    D=M
+    @R13
+    D=D&M
+    @{skip}
+    D;JEQ
+    @R13
+    M=M-D
+    @R14
+    D=M
+    @SP
+    A=M-1
+    M=D+M
+({skip})
+    @R14
+    D=M
+    M=D+M
+    @R15
+    D=M
+    M=D+M
+    @R13
+    D=M
+    @{start}
+    D;JNE
+({end})"
+            )
+        }
+
+        // R13 = -|x|, what's left to divide, R14 = -|y| doubled then the quotient,
+        // R15 = the top of the doubled divisors, x = a value with the sign of x / y
+        Op::Div => {
+            let (y_negative, x_negative) = (label("y_negative"), label("x_negative"));
+            let (sign, signed, divide, next, positive, zero) = (
+                label("sign"),
+                label("signed"),
+                label("divide"),
+                label("next"),
+                label("positive"),
+                label("zero"),
+            );
+
+            format!(
+                "    @SP
+    AM=M-1
+    D=M
+    @{zero}
+    D;JEQ
+    @R14
+    M=D
+    @{y_negative}
+    D;JLT
+    @R14
+    M=-D
+({y_negative})
+    @SP
+    A=M-1
+    D=M
+    @{zero}
+    D;JEQ
+    @R13
+    M=D
+    @{x_negative}
+    D;JLT
+    @R13
+    M=-D
+({x_negative})
+    @SP
+    A=M
+    D=M
+    @{sign}
+    D;JGE
+    @SP
+    A=M-1
+    M=!M
+({sign})
+    @SP
+    D=M
+    @R15
+    M=D
+({start})
+    @R14
+    D=M
+    @R15
+    M=M+1
+    A=M-1
+    M=D
+    @16384
+    D=D+A
+    @{divide}
+    D;JLT
+    @R14
+    D=M
+    D=D+M
+    @R13
+    D=D-M
+    @{divide}
+    D;JLT
+    @R14
+    D=M
+    M=D+M
+    @{start}
+    0;JMP
+({divide})
+    @R14
+    M=0
+({next})
+    @R14
+    D=M
+    M=D+M
+    @R15
+    AM=M-1
+    D=M
+    @R13
+    D=M-D
+    @{positive}
+    D;JGT
+    @R13
+    M=D
+    @R14
+    M=M+1
+({positive})
+    @R15
+    D=M
+    @SP
+    D=D-M
+    @{next}
+    D;JGT
+    @SP
+    A=M-1
+    D=M
+    @{signed}
+    D;JGE
+    @R14
+    M=-M
+({signed})
+    @R14
+    D=M
+    @SP
+    A=M-1
+    M=D
+    @{end}
+    0;JMP
+({zero})
+    @SP
+    A=M-1
+    M=0
+({end})"
+            )
+        }
+
+        // R13 = y, the shifts left
+        Op::Shl => format!(
+            "    @SP
+    AM=M-1
+    D=M
+    @R13
+    M=D
+({start})
+    @R13
+    MD=M-1
+    @{end}
+    D;JLT
+    @SP
+    A=M-1
+    D=M
+    M=D+M
+    @{start}
+    D;JNE
+({end})"
+        ),
+
+        // R13 = the bit of x to copy, R14 = the bit of the result it goes to,
+        // R15 = x. The bit to copy starts as 1 shifted y times, and the bits it
+        // runs out of are filled with the sign.
+        Op::Shr => {
+            let (shift, copy, skip, fill, fill_loop) = (
+                label("shift"),
+                label("copy"),
+                label("skip"),
+                label("fill"),
+                label("fill_loop"),
+            );
+
+            format!(
+                "    @SP
+    AM=M-1
+    D=M
+    @R13
+    M=D
+    @SP
+    A=M-1
+    D=M
+    @R15
+    M=D
+    @SP
+    A=M-1
+    M=0
+    @R14
+    M=1
+({shift})
+    @R13
+    MD=M-1
+    @{copy}
+    D;JLT
+    @R14
+    D=M
+    M=D+M
+    @{shift}
+    D;JNE
+({copy})
+    @R14
+    D=M
+    @R13
+    M=D
+    @R14
+    M=1
+({start})
+    @R13
+    D=M
+    @{fill}
+    D;JEQ
+    @R15
+    D=D&M
+    @{skip}
+    D;JEQ
+    @R14
+    D=M
+    @SP
+    A=M-1
+    M=D+M
+({skip})
+    @R14
+    D=M
+    M=D+M
+    @R13
+    D=M
+    M=D+M
+    @{start}
+    0;JMP
+({fill})
+    @R15
+    D=M
+    @{end}
+    D;JGE
+({fill_loop})
+    @R14
+    D=M
+    @{end}
+    D;JEQ
+    @SP
+    A=M-1
+    M=D+M
+    @R14
+    M=D+M
+    @{fill_loop}
+    0;JMP
+({end})"
+            )
+        }
+
+        _ => unreachable!("{op} is not computed by a loop"),
+    }
+}
+
+/// Pops both operands of the comparison and jumps to `target` if it's true.
+fn comparison_branch(op: Op, fast: bool, target: &str, label: impl Fn(&str) -> String) -> String {
+    let jump = jump(op);
+    let (difference, _) = difference(op, fast, &label);
+
+    format!(
+        "{difference}
+    @SP
+    M=M-1
+    @{target}
+    D;{jump}"
+    )
+}
+
+fn jump(op: Op) -> &'static str {
+    match op {
+        Op::Eq => "JEQ",
+        Op::Gt => "JGT",
+        Op::Lt => "JLT",
+        Op::Ge => "JGE",
+        Op::Le => "JLE",
+        Op::Ne => "JNE",
+        _ => unreachable!("{op} is not a comparison"),
+    }
+}
+
+/// Pops `y` and sets D to a value with the sign of `x - y`, leaving `x` on the stack.
+/// Also returns whether the code subtracts unconditionally, in which case A is left
+/// pointing to `x`.
+///
+/// Unless `fast` is set, `gt` and `lt` only subtract operands of the same sign, since
+/// `x - y` can overflow otherwise, and compare the signs instead.
+fn difference(op: Op, fast: bool, label: impl Fn(&str) -> String) -> (String, bool) {
+    // `eq` and `ne` can't go wrong, the difference is only 0 if the operands are equal
+    if fast || matches!(op, Op::Eq | Op::Ne) {
+        let code = "    @SP
+    AM=M-1
+    D=M
+    A=A-1
+    D=M-D";
+
+        return (code.to_owned(), true);
+    }
+
+    let y_negative = label("y_negative");
+    let subtract = label("subtract");
+    let sign = label("sign");
+
+    let code = format!(
+        "    @SP
+    AM=M-1
+    D=M
+    @{y_negative}
+    D;JLT
+    @SP
+    A=M-1
+    D=M
+    @{subtract}
+    D;JGE
+    D=-1
+    @{sign}
+    0;JMP             // x < 0 <= y
+({y_negative})
+    @SP
+    A=M-1
+    D=M
+    @{subtract}
+    D;JLT
+    D=1
+    @{sign}
+    0;JMP             // y < 0 <= x
+({subtract})
+    @SP
+    A=M
+    D=M
+    A=A-1
+    D=M-D             // x - y, operands have the same sign
+({sign})"
+    );
+
+    (code, false)
+}
+
+/// Expects the return address in D, nArgs in R13 and the function address in R14.
+/// The registers of the caller saved in a frame, after the return address.
+fn saved_registers(slim: bool) -> &'static [&'static str] {
+    if slim {
+        &["LCL", "ARG"]
+    } else {
+        &["LCL", "ARG", "THIS", "THAT"]
+    }
+}
+
+/// Calls the function in R14 with R13 arguments, returning to the address in D.
+fn call_routine(slim: bool) -> String {
+    let saved = saved_registers(slim);
+    let frame = saved.len() + 1;
+
+    let pushes: String = saved
+        .iter()
+        .map(|register| {
+            format!(
+                "
+    @{register}
+    D=M
+    @SP
+    AM=M+1
+    M=D // push {register}"
+            )
+        })
+        .collect();
+
+    format!(
+        "    @SP
+    A=M
+    M=D // push retAddr{pushes}
+    @SP
+    MD=M+1
+    @LCL
+    M=D // LCL = SP
+    @R13
+    D=D-M
+    @{frame}
+    D=D-A
+    @ARG
+    M=D // ARG = SP - {frame} - nArgs
+    @R14
+    A=M
+    0;JMP"
+    )
+}
+
+fn return_code(slim: bool) -> String {
+    let saved = saved_registers(slim);
+    let frame = saved.len() + 1;
+
+    // the registers are restored from the top of the frame
+    let restores: String = saved
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, register)| {
+            let offset = i + 1;
+
+            format!(
+                "
+    @R13
+    D=M
+    @{offset}
+    D=D-A
+    A=D
+    D=M
+    @{register}
+    M=D // {register} = *(endFrame - {offset})"
+            )
+        })
+        .collect();
+
+    format!(
+        "    @LCL
+    D=M
+    @R13
+    M=D // endFrame = LCL
+    @R13
+    D=M
+    @{frame}
+    D=D-A
+    A=D
+    D=M
+    @R14
+    M=D // retAddr = *(endFrame - {frame})
+    @SP
+    AM=M-1
+    D=M
+    @ARG
+    A=M
+    M=D // *ARG = pop()
+    D=A+1
+    @SP
+    M=D // SP = ARG + 1{restores}
+    @R14
+    A=M
+    0;JMP // goto retAddr"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    /// Translates a single .vm file with the options set by `configure`.
+    fn translate(input: &str, configure: impl FnOnce(&mut CodeWriter<&mut Vec<u8>>)) -> String {
+        let mut asm = vec![];
+        let mut code_writer = CodeWriter::with_output(&mut asm);
+        code_writer.set_module_name("Main.vm");
+        configure(&mut code_writer);
+
+        let commands = parser::parse("Main.vm", input).unwrap();
+        code_writer.write_commands(&commands).unwrap();
+        code_writer.close().unwrap();
+
+        String::from_utf8(asm).unwrap()
+    }
+
+    #[test]
+    fn shared_routines_are_written_once() {
+        let input = "function Main.main 0
+push constant 1
+push constant 2
+lt
+push constant 3
+eq
+call Main.main 0
+call Main.main 0
+return";
+
+        let asm = translate(input, |code_writer| code_writer.set_shared_routines(true));
+
+        for label in ["($$CALL)", "($$RETURN)", "($$EQ)", "($$LT)"] {
+            assert_eq!(asm.matches(label).count(), 1, "{label}");
+        }
+        assert!(!asm.contains("($$GT)"));
+        assert_eq!(asm.matches("@$$CALL").count(), 2);
+    }
+
+    #[test]
+    fn maps_commands_to_rom_addresses() {
+        let input = "function Main.main 1
+push constant 7
+push local 0
+push constant 1
+add
+pop local 0
+label LOOP
+goto LOOP";
+
+        let mut asm = vec![];
+        let mut code_writer = CodeWriter::with_output(&mut asm);
+        code_writer.set_source_map(true);
+        code_writer.set_module_name("Main.vm");
+
+        let commands = parser::parse("Main.vm", input).unwrap();
+        code_writer.write_commands(&commands).unwrap();
+        code_writer.close().unwrap();
+
+        let entries = code_writer.source_map().to_vec();
+        let asm = String::from_utf8(asm).unwrap();
+
+        let mut hack = vec![];
+        assembler::run(&asm, &mut hack).unwrap();
+        let instructions = std::str::from_utf8(&hack).unwrap().lines().count() as u16;
+
+        assert!(asm.contains("// Main.vm:2: push constant 7\n"));
+        assert!(asm.contains("// Main.vm:6: pop local 0\n"));
+
+        let lines: Vec<_> = entries.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [1, 2, 3, 8]);
+        assert_eq!(entries[0].rom.start, 0);
+        assert_eq!(entries[3].rom.end, instructions);
+
+        for pair in entries.windows(2) {
+            assert_eq!(pair[0].rom.end, pair[1].rom.start);
+        }
+
+        assert_eq!(
+            entries[1].to_string(),
+            format!("{} {} Main.vm 2", entries[1].rom.start, entries[1].rom.end)
+        );
+    }
+
+    #[test]
+    fn scopes_labels_to_functions() {
+        let input = "label START
+function Main.a 0
+label LOOP
+call Main.b 0
+goto LOOP
+function Main.b 0
+label LOOP
+call Main.a 0
+call Main.a 0
+goto LOOP";
+
+        let asm = translate(input, |_| {});
+
+        for label in [
+            "(Main$START)",
+            "(Main.a$LOOP)",
+            "(Main.a$ret.1)",
+            "(Main.b$LOOP)",
+            "(Main.b$ret.1)",
+            "(Main.b$ret.2)",
+        ] {
+            assert_eq!(asm.matches(label).count(), 1, "{label}");
+        }
+    }
+
+    /// VM code that pushes `value`, constants only go up to 32767.
+    fn push(value: i16) -> String {
+        match value {
+            0.. => format!("push constant {value}\n"),
+            i16::MIN => "push constant 32767\nneg\npush constant 1\nsub\n".to_owned(),
+            _ => format!("push constant {}\nneg\n", -value),
+        }
+    }
+
+    fn check_comparisons(configure: impl Fn(&mut CodeWriter<&mut Vec<u8>>)) {
+        let values = [i16::MIN, -32767, -2, -1, 0, 1, 2, 32766, i16::MAX];
+        let pairs: Vec<_> = values
+            .iter()
+            .flat_map(|&x| values.iter().map(move |&y| (x, y)))
+            .collect();
+
+        for op in ["gt", "lt", "eq", "ge", "le", "ne"] {
+            let mut input = String::new();
+
+            for (i, &(x, y)) in pairs.iter().enumerate() {
+                input += &format!("{}{}{op}\npop static {i}\n", push(x), push(y));
+            }
+
+            let mut cpu = crate::cpu::load(&input, &configure);
+            cpu.run(100_000);
+
+            for (i, &(x, y)) in pairs.iter().enumerate() {
+                let expected = op.parse::<Op>().unwrap().apply(x, y);
+
+                assert_eq!(cpu.ram[16 + i], expected, "{x} {op} {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn comparisons_do_not_overflow() {
+        check_comparisons(|_| {});
+        check_comparisons(|code_writer| code_writer.set_shared_routines(true));
+    }
+
+    #[test]
+    fn computes_loop_operations() {
+        let values = [i16::MIN, -32767, -300, -7, -1, 0, 1, 2, 7, 300, i16::MAX];
+        let shifts = [-1, 0, 1, 3, 15, 16, 17, i16::MAX];
+
+        for (op, ys) in [
+            ("mul", &values[..]),
+            ("div", &values),
+            ("shl", &shifts),
+            ("shr", &shifts),
+        ] {
+            let pairs: Vec<_> = values
+                .iter()
+                .flat_map(|&x| ys.iter().map(move |&y| (x, y)))
+                .collect();
+
+            let mut input = String::new();
+
+            for (i, &(x, y)) in pairs.iter().enumerate() {
+                input += &format!("{}{}{op}\npop static {i}\n", push(x), push(y));
+            }
+
+            let mut cpu = crate::cpu::load(&input, |_| {});
+            cpu.run(1_000_000);
+
+            assert_eq!(cpu.ram[0], 256);
+
+            for (i, &(x, y)) in pairs.iter().enumerate() {
+                let expected = op.parse::<Op>().unwrap().apply(x, y);
+
+                assert_eq!(cpu.ram[16 + i], expected, "{x} {op} {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn fast_comparisons_overflow() {
+        let input = format!("{}{}gt\npop static 0\n", push(i16::MAX), push(-1));
+
+        let mut cpu =
+            crate::cpu::load(&input, |code_writer| code_writer.set_fast_comparisons(true));
+        cpu.run(1000);
+
+        assert_eq!(cpu.ram[16], 0);
+    }
+
+    #[test]
+    fn branches_on_comparisons() {
+        let values = [i16::MIN, -1, 0, 1, i16::MAX];
+
+        for shared in [false, true] {
+            for op in ["gt", "lt", "eq", "ge", "le", "ne"] {
+                let mut input = String::new();
+                let mut expected = vec![];
+
+                for x in values {
+                    for y in values {
+                        let i = expected.len();
+                        input += &format!(
+                            "{}{}{op}\nif-goto TRUE{i}\ngoto END{i}\nlabel TRUE{i}\n\
+                            push constant 1\npop static {i}\nlabel END{i}\n",
+                            push(x),
+                            push(y)
+                        );
+                        expected.push(-op.parse::<Op>().unwrap().apply(x, y));
+                    }
+                }
+
+                let mut cpu = crate::cpu::load(&input, |code_writer| {
+                    code_writer.set_shared_routines(shared)
+                });
+                cpu.run(100_000);
+
+                assert_eq!(cpu.ram[16..16 + expected.len()], expected, "{op}");
+            }
+        }
+    }
+
+    #[test]
+    fn updates_in_place() {
+        let input = "push constant 3000
+pop pointer 0
+push constant 5
+pop this 2
+push this 2
+push constant 1
+add
+pop this 2
+push this 0
+push constant 1
+sub
+pop this 0
+push static 3
+push constant 100
+sub
+pop static 3
+push temp 1
+push constant 7
+add
+pop temp 1
+push constant 12
+push constant 10
+and
+push constant 3
+or";
+
+        let mut fused = crate::cpu::load(input, |_| {});
+        let steps = fused.run(1000);
+
+        let mut unfused = crate::cpu::load(input, |code_writer| {
+            code_writer.set_superinstructions(false)
+        });
+        let unfused_steps = unfused.run(1000);
+
+        assert_eq!(fused.ram[3002], 6);
+        assert_eq!(fused.ram[3000], -1);
+        assert_eq!(fused.ram[16], -100);
+        assert_eq!(fused.ram[6], 7);
+        assert_eq!(fused.ram[0], 257);
+        assert_eq!(fused.ram[256], 11);
+        for address in [0, 3, 6, 16, 256, 3000, 3002] {
+            assert_eq!(fused.ram[address], unfused.ram[address], "{address}");
+        }
+        assert!(steps < unfused_steps / 2, "{steps} {unfused_steps}");
+    }
+}
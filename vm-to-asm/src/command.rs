@@ -0,0 +1,254 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::ErrorKind;
+
+/// A VM command, as written in a .vm file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmCommand {
+    Arithmetic(Op),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function { name: String, n_vars: u16 },
+    Call { name: String, n_args: u16 },
+    Return,
+}
+
+/// A command and the line of the .vm file it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceCommand {
+    pub command: VmCommand,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Op {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+    /// `ge`, `le` and `ne` aren't part of the VM language, the optimizer produces
+    /// them for comparisons followed by `not`.
+    Ge,
+    Le,
+    Ne,
+    /// `mul`, `div`, `shl` and `shr` extend the VM language too, so that the
+    /// compiler can multiply and divide without calling `Math`, and are only parsed
+    /// when enabled. `shr` keeps the sign, and dividing by zero gives 0.
+    Mul,
+    Div,
+    Shl,
+    Shr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Constant,
+    Local,
+    Argument,
+    This,
+    That,
+    Pointer,
+    Temp,
+    Static,
+}
+
+impl VmCommand {
+    /// Checks the parts of a command its type can't rule out: popping to `constant`
+    /// and indices past the end of `pointer` and `temp`.
+    pub fn check(&self) -> Result<(), ErrorKind> {
+        let (segment, index) = match *self {
+            VmCommand::Pop(Segment::Constant, _) => {
+                return Err(ErrorKind::InvalidSegment(Segment::Constant.to_string()))
+            }
+            VmCommand::Push(segment, index) | VmCommand::Pop(segment, index) => (segment, index),
+            _ => return Ok(()),
+        };
+
+        match segment.size() {
+            Some(size) if index >= size => Err(ErrorKind::IndexOutOfRange {
+                segment: segment.to_string(),
+                index,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Op {
+    pub fn is_unary(self) -> bool {
+        matches!(self, Op::Neg | Op::Not)
+    }
+
+    pub fn is_comparison(self) -> bool {
+        self.negated().is_some()
+    }
+
+    /// The comparison with the opposite result.
+    pub fn negated(self) -> Option<Op> {
+        let op = match self {
+            Op::Eq => Op::Ne,
+            Op::Ne => Op::Eq,
+            Op::Gt => Op::Le,
+            Op::Le => Op::Gt,
+            Op::Lt => Op::Ge,
+            Op::Ge => Op::Lt,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    /// Computes `x op y`, or `op y` for unary operators. Comparisons are -1 when
+    /// true and 0 when false.
+    pub fn apply(self, x: i16, y: i16) -> i16 {
+        match self {
+            Op::Add => x.wrapping_add(y),
+            Op::Sub => x.wrapping_sub(y),
+            Op::Neg => y.wrapping_neg(),
+            Op::And => x & y,
+            Op::Or => x | y,
+            Op::Not => !y,
+            Op::Eq => -((x == y) as i16),
+            Op::Gt => -((x > y) as i16),
+            Op::Lt => -((x < y) as i16),
+            Op::Ge => -((x >= y) as i16),
+            Op::Le => -((x <= y) as i16),
+            Op::Ne => -((x != y) as i16),
+            Op::Mul => x.wrapping_mul(y),
+            Op::Div if y == 0 => 0,
+            Op::Div => x.wrapping_div(y),
+            Op::Shl if y >= 16 => 0,
+            Op::Shl | Op::Shr if y <= 0 => x,
+            Op::Shl => x << y,
+            Op::Shr => x >> y.min(15),
+        }
+    }
+}
+
+impl Segment {
+    /// Number of entries of the segments with a fixed size.
+    pub fn size(self) -> Option<u16> {
+        match self {
+            Segment::Pointer => Some(2),
+            Segment::Temp => Some(8),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VmCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use VmCommand::*;
+
+        match self {
+            Arithmetic(op) => write!(f, "{op}"),
+            Push(segment, index) => write!(f, "push {segment} {index}"),
+            Pop(segment, index) => write!(f, "pop {segment} {index}"),
+            Label(label) => write!(f, "label {label}"),
+            Goto(label) => write!(f, "goto {label}"),
+            IfGoto(label) => write!(f, "if-goto {label}"),
+            Function { name, n_vars } => write!(f, "function {name} {n_vars}"),
+            Call { name, n_args } => write!(f, "call {name} {n_args}"),
+            Return => write!(f, "return"),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Neg => "neg",
+            Op::Eq => "eq",
+            Op::Gt => "gt",
+            Op::Lt => "lt",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Not => "not",
+            Op::Ge => "ge",
+            Op::Le => "le",
+            Op::Ne => "ne",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Shl => "shl",
+            Op::Shr => "shr",
+        };
+
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let segment = match self {
+            Segment::Constant => "constant",
+            Segment::Local => "local",
+            Segment::Argument => "argument",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+            Segment::Static => "static",
+        };
+
+        write!(f, "{segment}")
+    }
+}
+
+impl FromStr for Op {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let op = match s {
+            "add" => Op::Add,
+            "sub" => Op::Sub,
+            "neg" => Op::Neg,
+            "eq" => Op::Eq,
+            "gt" => Op::Gt,
+            "lt" => Op::Lt,
+            "and" => Op::And,
+            "or" => Op::Or,
+            "not" => Op::Not,
+            "ge" => Op::Ge,
+            "le" => Op::Le,
+            "ne" => Op::Ne,
+            "mul" => Op::Mul,
+            "div" => Op::Div,
+            "shl" => Op::Shl,
+            "shr" => Op::Shr,
+            c => return Err(ErrorKind::UnknownCommand(c.to_owned())),
+        };
+
+        Ok(op)
+    }
+}
+
+impl FromStr for Segment {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segment = match s {
+            "constant" => Segment::Constant,
+            "local" => Segment::Local,
+            "argument" => Segment::Argument,
+            "this" => Segment::This,
+            "that" => Segment::That,
+            "pointer" => Segment::Pointer,
+            "temp" => Segment::Temp,
+            "static" => Segment::Static,
+            s => return Err(ErrorKind::InvalidSegment(s.to_owned())),
+        };
+
+        Ok(segment)
+    }
+}
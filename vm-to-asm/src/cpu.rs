@@ -0,0 +1,158 @@
+//! A Hack CPU to run the translator's output in tests.
+
+use crate::code_writer::CodeWriter;
+use crate::command::{SourceCommand, VmCommand};
+use crate::emulator::{RAM_SIZE, SP};
+use crate::parser;
+
+pub struct Cpu {
+    rom: Vec<u16>,
+    pub ram: Vec<i16>,
+    pc: usize,
+    a: i16,
+    d: i16,
+}
+
+impl Cpu {
+    /// Loads the binary text lines written by the assembler.
+    pub fn new(hack: &str) -> Self {
+        let rom = hack
+            .lines()
+            .map(|l| u16::from_str_radix(l, 2).expect("assembler output is binary"))
+            .collect();
+
+        Cpu {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            a: 0,
+            d: 0,
+        }
+    }
+
+    /// Runs until the program counter leaves the ROM or `max_steps` instructions
+    /// have been executed, returning the number of executed instructions.
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        let mut steps = 0;
+
+        while steps < max_steps && self.pc < self.rom.len() {
+            self.step();
+            steps += 1;
+        }
+
+        steps
+    }
+
+    /// Runs until `done` holds, returning the number of executed instructions, or
+    /// `None` if it still doesn't after `max_steps`.
+    pub fn run_until(&mut self, max_steps: usize, done: impl Fn(&Cpu) -> bool) -> Option<usize> {
+        for steps in 0..max_steps {
+            if done(self) {
+                return Some(steps);
+            }
+
+            self.step();
+        }
+
+        None
+    }
+
+    fn step(&mut self) {
+        let instruction = self.rom[self.pc];
+        self.pc += 1;
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            return;
+        }
+
+        let address = self.a as u16 as usize;
+        let y = if instruction & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+
+        let out = alu(self.d, y, (instruction >> 6) as u8 & 0x3f);
+
+        if instruction & 0b001_000 != 0 {
+            self.ram[address] = out;
+        }
+        if instruction & 0b100_000 != 0 {
+            self.a = out;
+        }
+        if instruction & 0b010_000 != 0 {
+            self.d = out;
+        }
+
+        let jump = instruction & 0b111;
+        let taken = (jump & 0b100 != 0 && out < 0)
+            || (jump & 0b010 != 0 && out == 0)
+            || (jump & 0b001 != 0 && out > 0);
+
+        if taken {
+            self.pc = self.a as u16 as usize;
+        }
+    }
+}
+
+/// The Hack ALU, `control` holds the bits zx nx zy ny f no.
+fn alu(x: i16, y: i16, control: u8) -> i16 {
+    let bit = |n: u8| control & (1 << (5 - n)) != 0;
+
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+
+    if bit(5) {
+        !out
+    } else {
+        out
+    }
+}
+
+/// Translates a single .vm file with the options set by `configure`, assembles it and
+/// loads it with the stack pointer set.
+pub fn load(input: &str, configure: impl FnOnce(&mut CodeWriter<&mut Vec<u8>>)) -> Cpu {
+    let mut asm = vec![];
+    let mut code_writer = CodeWriter::with_output(&mut asm);
+    code_writer.set_module_name("Main.vm");
+    configure(&mut code_writer);
+
+    // `ge`, `le` and `ne` only come out of the optimizer, the parser rejects them
+    let commands: Vec<_> = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let command = match line.trim().parse() {
+                Ok(op) => VmCommand::Arithmetic(op),
+                Err(_) => parser::parse_with("Main.vm", line, true).unwrap()[0]
+                    .command
+                    .clone(),
+            };
+
+            SourceCommand {
+                command,
+                line: n + 1,
+            }
+        })
+        .collect();
+    code_writer.write_commands(&commands).unwrap();
+    code_writer.close().unwrap();
+
+    let mut cpu = assemble(std::str::from_utf8(&asm).unwrap());
+    cpu.ram[SP] = 256;
+
+    cpu
+}
+
+/// Assembles a Hack assembly program and loads it.
+pub fn assemble(asm: &str) -> Cpu {
+    let mut hack = vec![];
+    assembler::run(asm, &mut hack).unwrap();
+
+    Cpu::new(std::str::from_utf8(&hack).unwrap())
+}
@@ -0,0 +1,752 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::code_writer::CodeWriter;
+use crate::command::{Op, Segment, SourceCommand, VmCommand};
+
+/// VM code lifted back from assembly written by `CodeWriter`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Disassembly {
+    /// Whether the program starts with the bootstrap code.
+    pub bootstrap: bool,
+    /// The commands of each .vm file. A file is named after the class of its
+    /// functions, the commands before the first function go to the file named
+    /// after the program.
+    pub modules: Vec<(String, Vec<VmCommand>)>,
+    /// The lines no command translates to, numbered from 1.
+    pub unmatched: Vec<Range<usize>>,
+}
+
+/// Lifts the assembly of a program translated by `CodeWriter` back into VM commands,
+/// in any of its modes. `module` names the file the commands before the first
+/// function come from.
+///
+/// The code of a command is recognized by translating the commands it could be and
+/// comparing their code with the program, up to the names of the labels the
+/// translator makes up, like return addresses. So only code the translator writes
+/// is recognized, any other line is reported in `unmatched`.
+pub fn disassemble(module: &str, asm: &str) -> Disassembly {
+    let mut disassembler = Disassembler {
+        lines: lines(asm),
+        module: module.to_owned(),
+        function: None,
+    };
+
+    let mut disassembly = Disassembly {
+        modules: vec![(module.to_owned(), vec![])],
+        ..Default::default()
+    };
+
+    let mut start = 0;
+
+    while start < disassembler.lines.len() {
+        let Some((len, candidate)) = disassembler.longest_match(start) else {
+            let number = disassembler.lines[start].number;
+
+            match disassembly.unmatched.last_mut() {
+                Some(lines) if lines.end == number => lines.end = number + 1,
+                _ => disassembly.unmatched.push(number..number + 1),
+            }
+
+            start += 1;
+            continue;
+        };
+
+        match candidate {
+            Candidate::Bootstrap => disassembly.bootstrap = true,
+            Candidate::Routine(_) => {}
+            Candidate::Commands(commands) => {
+                for command in commands {
+                    if let VmCommand::Function { name, .. } = &command {
+                        let class = name.split('.').next().unwrap_or(name);
+
+                        if class != disassembler.module {
+                            disassembler.module = class.to_owned();
+                            disassembly.modules.push((class.to_owned(), vec![]));
+                        }
+
+                        disassembler.function = Some(name.clone());
+                    }
+
+                    let (_, commands) = disassembly.modules.last_mut().expect("a module");
+                    commands.push(command);
+                }
+            }
+        }
+
+        start += len;
+    }
+
+    disassembly
+        .modules
+        .retain(|(_, commands)| !commands.is_empty());
+    disassembly
+}
+
+/// An instruction or a label, without whitespace and comments.
+#[derive(Debug)]
+struct Line {
+    text: String,
+    number: usize,
+}
+
+/// What some lines of assembly can be the code of.
+#[derive(Debug)]
+enum Candidate {
+    Commands(Vec<VmCommand>),
+    Bootstrap,
+    /// A shared routine, labeled `$$NAME`.
+    Routine(String),
+}
+
+/// The modes of `CodeWriter` that change the code of a command.
+#[derive(Debug, Clone, Copy)]
+struct Options {
+    shared: bool,
+    fast: bool,
+    slim: bool,
+}
+
+struct Disassembler {
+    lines: Vec<Line>,
+    module: String,
+    function: Option<String>,
+}
+
+impl Disassembler {
+    /// The candidate whose code covers the most lines from `start`, and that number.
+    fn longest_match(&self, start: usize) -> Option<(usize, Candidate)> {
+        let lines = &self.lines[start..];
+
+        self.candidates(lines)
+            .into_iter()
+            .filter_map(|candidate| Some((self.match_len(&candidate, lines)?, candidate)))
+            .max_by_key(|(len, _)| *len)
+    }
+
+    /// Number of lines at the start of `lines` the code of `candidate` covers, in one
+    /// of the modes it can be translated in.
+    fn match_len(&self, candidate: &Candidate, lines: &[Line]) -> Option<usize> {
+        let variants = |shared: &[bool], fast: &[bool], slim: &[bool]| {
+            let mut options = vec![];
+
+            for &shared in shared {
+                for &fast in fast {
+                    for &slim in slim {
+                        options.push(Options { shared, fast, slim });
+                    }
+                }
+            }
+
+            options
+        };
+
+        match candidate {
+            Candidate::Bootstrap => variants(&[false, true], &[false], &[false, true])
+                .into_iter()
+                .filter_map(|options| self.translate(&[], options, true))
+                .map(|code| until_routines(&code).to_vec())
+                .find(|code| matches(code, lines, &[])),
+            Candidate::Routine(name) if name == "$$END" => Some(vec![
+                "($$END)".to_owned(),
+                "@$$END".to_owned(),
+                "0;JMP".to_owned(),
+            ])
+            .filter(|code| matches(code, lines, &[])),
+            Candidate::Routine(name) => {
+                let (commands, slim) = routine_commands(name)?;
+
+                variants(&[true], &[false, true], &[slim])
+                    .into_iter()
+                    .filter_map(|options| self.translate(&commands, options, false))
+                    .filter_map(|code| routine(&code, name).map(<[_]>::to_vec))
+                    .find(|code| matches(code, lines, std::slice::from_ref(name)))
+            }
+            Candidate::Commands(commands) => {
+                let calls = commands
+                    .iter()
+                    .any(|c| matches!(c, VmCommand::Call { .. } | VmCommand::Return));
+                let compares = commands
+                    .iter()
+                    .any(|c| matches!(c, VmCommand::Arithmetic(op) if op.is_comparison()));
+
+                let both: &[bool] = &[false, true];
+                let literals = self.literals(commands);
+
+                variants(
+                    if calls || compares { both } else { &[false] },
+                    if compares { both } else { &[false] },
+                    if calls { both } else { &[false] },
+                )
+                .into_iter()
+                .filter_map(|options| self.translate(commands, options, false))
+                .map(|code| until_routines(&code).to_vec())
+                .find(|code| matches(code, lines, &literals))
+            }
+        }
+        .map(|code| code.len())
+    }
+
+    /// The labels of `commands` that keep their names in the code: functions and
+    /// the labels of the VM code.
+    fn literals(&self, commands: &[VmCommand]) -> Vec<String> {
+        let mut scope = self.scope().to_owned();
+        let mut literals = vec![];
+
+        for command in commands {
+            match command {
+                VmCommand::Function { name, .. } => {
+                    scope = name.clone();
+                    literals.push(name.clone());
+                }
+                VmCommand::Label(label) | VmCommand::Goto(label) | VmCommand::IfGoto(label) => {
+                    literals.push(format!("{scope}${label}"))
+                }
+                _ => {}
+            }
+        }
+
+        literals
+    }
+
+    /// Translates `commands` in the current function, or the bootstrap code, returning
+    /// the lines of the code after the function.
+    fn translate(
+        &self,
+        commands: &[VmCommand],
+        options: Options,
+        init: bool,
+    ) -> Option<Vec<String>> {
+        let mut asm = vec![];
+        let mut code_writer = CodeWriter::with_output(&mut asm);
+
+        code_writer.set_shared_routines(options.shared);
+        code_writer.set_fast_comparisons(options.fast);
+
+        if options.slim {
+            let slim = commands.iter().filter_map(|command| match command {
+                VmCommand::Call { name, .. } => Some(name.clone()),
+                _ => None,
+            });
+
+            code_writer.set_slim_frames(
+                slim.chain(self.function.clone())
+                    .chain(init.then(|| "Sys.init".to_owned()))
+                    .collect(),
+            );
+        }
+
+        let mut skip = 0;
+
+        if init {
+            code_writer.write_init().ok()?;
+        } else {
+            code_writer.set_module_name(&format!("{}.vm", self.module));
+
+            if let Some(function) = &self.function {
+                code_writer.write_function(function, 0).ok()?;
+                skip = 1;
+            }
+
+            let commands: Vec<_> = commands
+                .iter()
+                .map(|command| SourceCommand {
+                    command: command.clone(),
+                    line: 0,
+                })
+                .collect();
+
+            code_writer.write_commands(&commands).ok()?;
+        }
+
+        code_writer.close().ok()?;
+
+        let asm = String::from_utf8(asm).ok()?;
+
+        Some(lines(&asm).into_iter().skip(skip).map(|l| l.text).collect())
+    }
+
+    /// The commands whose code can start with the first of `lines`, guessed from the
+    /// names and numbers in the next few lines.
+    fn candidates(&self, lines: &[Line]) -> Vec<Candidate> {
+        use VmCommand::*;
+
+        let line = |i: usize| lines.get(i).map_or("", |l| l.text.as_str());
+        let mut candidates = vec![];
+
+        let mut push_commands =
+            |commands: Vec<VmCommand>| candidates.push(Candidate::Commands(commands));
+
+        if let Some(name) = label(line(0)) {
+            if name.starts_with("$$") && !name.contains('.') {
+                return vec![Candidate::Routine(name.to_owned())];
+            }
+
+            if let Some(label) = self.local_label(name) {
+                return vec![Candidate::Commands(vec![Label(label.to_owned())])];
+            }
+
+            let mut n_vars = 0;
+
+            if line(1) == "D=0" {
+                while (0..5).all(|i| line(2 + 5 * n_vars + i) == PUSH_D[i]) {
+                    n_vars += 1;
+                }
+            }
+
+            push_commands(vec![Function {
+                name: name.to_owned(),
+                n_vars: n_vars as u16,
+            }]);
+        } else if let Some(n) = number(line(0)) {
+            push_commands(vec![Push(Segment::Constant, n)]);
+
+            for op in [Op::Add, Op::Sub, Op::And, Op::Or] {
+                push_commands(vec![Push(Segment::Constant, n), Arithmetic(op)]);
+            }
+
+            for (segment, index) in self.addresses(&lines[2.min(lines.len())..]) {
+                for op in [Op::Add, Op::Sub] {
+                    push_commands(in_place(segment, index, op, n));
+                }
+            }
+
+            if let Some(name) = symbol(line(4)) {
+                push_commands(vec![Call {
+                    name: name.to_owned(),
+                    n_args: n,
+                }]);
+            }
+
+            if n == 256 {
+                candidates.push(Candidate::Bootstrap);
+            }
+        } else if line(0) == "@SP" {
+            for op in OPS {
+                push_commands(vec![Arithmetic(op)]);
+            }
+
+            for op in [Op::Add, Op::Sub] {
+                push_commands(vec![Push(Segment::Constant, 1), Arithmetic(op)]);
+            }
+
+            for (segment, index) in self.addresses(&lines[3.min(lines.len())..]) {
+                push_commands(vec![Pop(segment, index)]);
+            }
+
+            let labels: HashSet<_> = lines
+                .iter()
+                .take(40)
+                .filter_map(|l| self.local_label(symbol(&l.text)?))
+                .collect();
+
+            for label in labels {
+                push_commands(vec![IfGoto(label.to_owned())]);
+
+                for op in OPS.into_iter().filter(|op| op.is_comparison()) {
+                    push_commands(vec![Arithmetic(op), IfGoto(label.to_owned())]);
+                }
+            }
+        } else if let Some(name) = symbol(line(0)) {
+            let addresses = self.addresses(lines);
+
+            for &(segment, index) in &addresses {
+                push_commands(vec![Push(segment, index)]);
+                push_commands(vec![Pop(segment, index)]);
+
+                for op in [Op::Add, Op::Sub] {
+                    push_commands(in_place(segment, index, op, 1));
+                }
+            }
+
+            if name == "LCL" {
+                push_commands(vec![Return]);
+            }
+
+            if addresses.is_empty() && line(1) == "0;JMP" {
+                match self.local_label(name) {
+                    Some(label) => push_commands(vec![Goto(label.to_owned())]),
+                    None => push_commands(vec![Return]),
+                }
+            }
+
+            if addresses.is_empty() && line(1) == "D=A" {
+                // shared comparisons jump to `$$OP`
+                if let Some(op) = symbol(line(2))
+                    .and_then(|routine| routine.strip_prefix("$$"))
+                    .and_then(|op| op.to_lowercase().parse().ok())
+                {
+                    push_commands(vec![Arithmetic(op)]);
+                }
+
+                // the function jumped to, after pushing the frame and setting ARG
+                let jump = lines.iter().take(80).position(|l| l.text == "0;JMP");
+                let arg =
+                    (0..80.min(lines.len())).find(|&i| line(i) == "@ARG" && line(i + 1) == "M=D");
+
+                if let (Some(jump), Some(arg)) = (jump, arg) {
+                    if let (Some(name), Some(n_args)) = (
+                        symbol(line(jump.saturating_sub(1))),
+                        number(line(arg.saturating_sub(2))),
+                    ) {
+                        push_commands(vec![Call {
+                            name: name.to_owned(),
+                            n_args,
+                        }]);
+                    }
+                }
+            }
+        }
+
+        candidates
+    }
+
+    /// The segments and indices the address at the start of `lines` can be for.
+    fn addresses(&self, lines: &[Line]) -> Vec<(Segment, u16)> {
+        let line = |i: usize| lines.get(i).map_or("", |l| l.text.as_str());
+        let mut addresses = vec![];
+
+        let Some(name) = symbol(line(0)) else {
+            return addresses;
+        };
+
+        let segment = match name {
+            "LCL" => Some(Segment::Local),
+            "ARG" => Some(Segment::Argument),
+            "THIS" => Some(Segment::This),
+            "THAT" => Some(Segment::That),
+            _ => None,
+        };
+
+        if let Some(segment) = segment {
+            addresses.push((segment, number(line(2)).unwrap_or(0)));
+        }
+
+        match name {
+            "THIS" => addresses.push((Segment::Pointer, 0)),
+            "THAT" => addresses.push((Segment::Pointer, 1)),
+            _ => {}
+        }
+
+        if let Some(index) = name.strip_prefix('R').and_then(|r| r.parse::<u16>().ok()) {
+            if (5..13).contains(&index) {
+                addresses.push((Segment::Temp, index - 5));
+            }
+        }
+
+        if let Some((module, index)) = name.rsplit_once('.') {
+            if let (true, Ok(index)) = (module == self.module, index.parse()) {
+                addresses.push((Segment::Static, index));
+            }
+        }
+
+        addresses
+    }
+
+    /// The VM label a label of the code is for, in the current function.
+    fn local_label<'a>(&self, name: &'a str) -> Option<&'a str> {
+        name.strip_prefix(self.scope())?.strip_prefix('$')
+    }
+
+    fn scope(&self) -> &str {
+        self.function.as_deref().unwrap_or(&self.module)
+    }
+}
+
+/// The operations a command can apply to the top of the stack.
+const OPS: [Op; 16] = [
+    Op::Add,
+    Op::Sub,
+    Op::Neg,
+    Op::Eq,
+    Op::Gt,
+    Op::Lt,
+    Op::And,
+    Op::Or,
+    Op::Not,
+    Op::Ge,
+    Op::Le,
+    Op::Ne,
+    Op::Mul,
+    Op::Div,
+    Op::Shl,
+    Op::Shr,
+];
+
+/// Pushes D, as `CodeWriter` does.
+const PUSH_D: [&str; 5] = ["@SP", "A=M", "M=D", "@SP", "M=M+1"];
+
+fn in_place(segment: Segment, index: u16, op: Op, n: u16) -> Vec<VmCommand> {
+    vec![
+        VmCommand::Push(segment, index),
+        VmCommand::Push(Segment::Constant, n),
+        VmCommand::Arithmetic(op),
+        VmCommand::Pop(segment, index),
+    ]
+}
+
+/// The commands that make `CodeWriter` write the shared routine `name`, and whether
+/// it uses slim frames.
+fn routine_commands(name: &str) -> Option<(Vec<VmCommand>, bool)> {
+    let call = |slim| {
+        let call = VmCommand::Call {
+            name: "Sys.init".to_owned(),
+            n_args: 0,
+        };
+
+        Some((vec![call], slim))
+    };
+
+    match name {
+        "$$CALL" => call(false),
+        "$$SLIM_CALL" => call(true),
+        "$$RETURN" => Some((vec![VmCommand::Return], false)),
+        "$$SLIM_RETURN" => Some((vec![VmCommand::Return], true)),
+        _ => {
+            let op = name.strip_prefix("$$")?.to_lowercase().parse().ok()?;
+            Some((vec![VmCommand::Arithmetic(op)], false))
+        }
+    }
+}
+
+/// The code of the shared routine `name` in a whole program.
+fn routine<'a>(code: &'a [String], name: &str) -> Option<&'a [String]> {
+    let start = code.iter().position(|l| label(l) == Some(name))?;
+    let len = code[start + 1..]
+        .iter()
+        .position(
+            |l| matches!(label(l), Some(name) if name.starts_with("$$") && !name.contains('.')),
+        )
+        .map_or(code.len() - start, |len| len + 1);
+
+    Some(&code[start..start + len])
+}
+
+/// The code before the shared routines.
+fn until_routines(code: &[String]) -> &[String] {
+    let end = code
+        .iter()
+        .position(|l| l == "($$END)")
+        .unwrap_or(code.len());
+    &code[..end]
+}
+
+/// Whether `lines` start with `code`, once the labels `code` defines are renamed to
+/// the labels of `lines`, except for `literals`.
+fn matches(code: &[String], lines: &[Line], literals: &[String]) -> bool {
+    if code.len() > lines.len() {
+        return false;
+    }
+
+    let defined: HashSet<_> = code
+        .iter()
+        .filter_map(|l| label(l))
+        .filter(|name| !literals.iter().any(|literal| literal == name))
+        .collect();
+
+    let mut renames: HashMap<&str, &str> = HashMap::new();
+
+    code.iter().zip(lines).all(|(expected, line)| {
+        let names = match (label(expected), label(&line.text)) {
+            (Some(a), Some(b)) => Some((a, b)),
+            _ => symbol(expected).zip(symbol(&line.text)),
+        };
+
+        match names {
+            Some((a, b)) if defined.contains(a) => *renames.entry(a).or_insert(b) == b,
+            _ => *expected == line.text,
+        }
+    })
+}
+
+/// The instructions and labels of `asm`, without whitespace and comments.
+fn lines(asm: &str) -> Vec<Line> {
+    asm.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let code = line.split("//").next().unwrap_or_default();
+            let text: String = code.split_whitespace().collect();
+
+            (!text.is_empty()).then_some(Line {
+                text,
+                number: i + 1,
+            })
+        })
+        .collect()
+}
+
+fn label(line: &str) -> Option<&str> {
+    line.strip_prefix('(')?.strip_suffix(')')
+}
+
+/// The name in an A-instruction, if it's not a number.
+fn symbol(line: &str) -> Option<&str> {
+    line.strip_prefix('@')
+        .filter(|name| !name.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+fn number(line: &str) -> Option<u16> {
+    line.strip_prefix('@')?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    /// Translates `modules` with the options set by `configure`, with the bootstrap code.
+    fn translate(
+        modules: &[(&str, Vec<SourceCommand>)],
+        configure: impl FnOnce(&mut CodeWriter<&mut Vec<u8>>),
+    ) -> String {
+        let mut asm = vec![];
+        let mut code_writer = CodeWriter::with_output(&mut asm);
+        configure(&mut code_writer);
+
+        code_writer.write_init().unwrap();
+
+        for (file, commands) in modules {
+            code_writer.set_module_name(file);
+            code_writer.write_commands(commands).unwrap();
+        }
+
+        code_writer.close().unwrap();
+
+        String::from_utf8(asm).unwrap()
+    }
+
+    #[test]
+    fn lifts_every_mode() {
+        let main = "function Main.main 2
+push constant 7
+push constant 300
+mul
+pop local 1
+push local 1
+push constant 1
+add
+pop local 1
+push static 2
+push constant 5
+sub
+pop static 2
+label LOOP
+push argument 0
+push constant 3
+gt
+if-goto END
+push local 1
+push constant 2
+and
+push local 0
+push constant 1
+lt
+not
+if-goto LOOP
+push argument 1
+push constant 0
+eq
+pop temp 3
+push pointer 1
+pop that 4
+push this 0
+call Main.double 1
+goto LOOP
+label END
+push constant 0
+return
+function Main.double 0
+push argument 0
+push argument 0
+add
+return";
+        let sys = "function Sys.init 0
+push constant 4
+push constant 2
+div
+push constant 1
+shl
+push constant 3
+shr
+neg
+call Main.main 2
+label HALT
+goto HALT";
+
+        let modules = [
+            (
+                "Main.vm",
+                parser::parse_with("Main.vm", main, true).unwrap(),
+            ),
+            ("Sys.vm", parser::parse_with("Sys.vm", sys, true).unwrap()),
+        ];
+
+        let expected: Vec<_> = modules
+            .iter()
+            .map(|(file, commands)| {
+                let commands = commands.iter().map(|c| c.command.clone()).collect();
+                (file.strip_suffix(".vm").unwrap().to_owned(), commands)
+            })
+            .collect();
+
+        let configurations: [fn(&mut CodeWriter<&mut Vec<u8>>); 5] = [
+            |_| {},
+            |code_writer| code_writer.set_superinstructions(false),
+            |code_writer| code_writer.set_shared_routines(true),
+            |code_writer| {
+                code_writer.set_shared_routines(true);
+                code_writer.set_fast_comparisons(true);
+            },
+            |code_writer| {
+                code_writer.set_slim_frames(["Main.double".to_owned()].into());
+                code_writer.set_fast_comparisons(true);
+            },
+        ];
+
+        for configure in configurations {
+            let asm = translate(&modules, configure);
+            let disassembly = disassemble("Prog", &asm);
+
+            assert_eq!(disassembly.unmatched, [], "{asm}");
+            assert!(disassembly.bootstrap);
+            assert_eq!(disassembly.modules, expected);
+        }
+    }
+
+    #[test]
+    fn reports_unmatched_lines() {
+        let asm = "// pushes 1
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+M=M+1 // grows the stack, not a command
+
+@Main.2
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1";
+
+        let disassembly = disassemble("Main", asm);
+
+        assert_eq!(
+            disassembly.modules,
+            [(
+                "Main".to_owned(),
+                vec![
+                    VmCommand::Push(Segment::Constant, 1),
+                    VmCommand::Push(Segment::Static, 2)
+                ]
+            )]
+        );
+        assert_eq!(disassembly.unmatched.len(), 1);
+        assert_eq!(disassembly.unmatched[0], 9..11);
+    }
+}
@@ -0,0 +1,1042 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::{error, fmt, mem};
+
+use crate::command::{Op, Segment, SourceCommand, VmCommand};
+use crate::error::VmError;
+use crate::parser;
+
+mod os;
+
+pub const RAM_SIZE: usize = 32768;
+
+pub const SP: usize = 0;
+pub const LCL: usize = 1;
+pub const ARG: usize = 2;
+pub const THIS: usize = 3;
+pub const THAT: usize = 4;
+
+const TEMP: usize = 5;
+const STATIC: usize = 16;
+const STACK: i16 = 256;
+const HEAP: i16 = 2048;
+const KBD: i32 = 24576;
+
+/// Return address pushed when a built-in function calls back into VM code.
+const NATIVE: i16 = -1;
+
+pub type Result<T = ()> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The errors found in a file that couldn't be loaded.
+    Parse(Vec<VmError>),
+    DuplicateFunction(String),
+    UndefinedLabel {
+        function: String,
+        label: String,
+    },
+    UndefinedFunction(String),
+    ArgumentCount {
+        function: String,
+        expected: u16,
+        found: u16,
+    },
+    InvalidAddress(i32),
+    StackUnderflow,
+    StackOverflow,
+    SegmentIndex {
+        segment: Segment,
+        index: u16,
+        size: u16,
+    },
+    NullPointer(Segment),
+    /// `Memory.deAlloc` of something that isn't an allocated heap block.
+    InvalidFree(i16),
+    ReadOnly(i32),
+    EndOfInput,
+    /// Raised inside built-in functions when the steps given to `run` are used up, to
+    /// unwind them; `run` returns normally.
+    StepLimit,
+    /// Raised by `Sys.halt` to unwind the built-in functions that are running.
+    Halted,
+    /// A runtime error and the command that caused it.
+    At(Location, Box<Error>),
+}
+
+/// The VM command being executed.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: String,
+    pub function: String,
+    /// Line of the command in the file.
+    pub line: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+
+        match self {
+            Parse(errors) => {
+                let errors = errors.iter().map(ToString::to_string);
+                write!(f, "{}", errors.collect::<Vec<_>>().join("\n"))
+            }
+            DuplicateFunction(n) => write!(f, "function `{n}` is already defined"),
+            UndefinedLabel { function, label } => {
+                write!(f, "label `{label}` is not defined in `{function}`")
+            }
+            UndefinedFunction(n) => write!(f, "function `{n}` is not defined"),
+            ArgumentCount {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function `{function}` takes {expected} arguments but {found} were supplied"
+            ),
+            InvalidAddress(a) => write!(f, "address {a} is out of range"),
+            StackUnderflow => write!(f, "stack underflow"),
+            StackOverflow => write!(f, "stack overflow into the heap"),
+            SegmentIndex {
+                segment,
+                index,
+                size,
+            } => write!(
+                f,
+                "index {index} is out of range for `{segment}`, which has {size} entries"
+            ),
+            NullPointer(segment) => write!(f, "`{segment}` accessed through a null pointer"),
+            InvalidFree(a) => write!(f, "{a} is not an allocated block and can't be freed"),
+            ReadOnly(a) => write!(f, "write to read-only address {a}"),
+            EndOfInput => write!(f, "no more keyboard input"),
+            StepLimit => write!(f, "step limit reached inside a built-in function"),
+            Halted => write!(f, "program halted"),
+            At(location, error) => write!(
+                f,
+                "{error}, in `{}` ({}:{})",
+                location.function, location.file, location.line
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Vec<VmError>> for Error {
+    fn from(errors: Vec<VmError>) -> Self {
+        Error::Parse(errors)
+    }
+}
+
+/// Where an instruction was loaded from, see [`Location`].
+#[derive(Debug, Clone)]
+struct Site {
+    file: Rc<str>,
+    function: Rc<str>,
+    line: usize,
+}
+
+/// The part of a call frame the checks need.
+#[derive(Debug, Default)]
+struct Frame {
+    /// Unknown when execution starts inside a function without calling it.
+    n_args: Option<u16>,
+    n_vars: u16,
+}
+
+/// A loaded [`VmCommand`], with jump targets resolved to command indices and
+/// `static` indices replaced by the RAM address the assembler would have
+/// allocated to the variable.
+#[derive(Debug, Clone)]
+enum Instruction {
+    Arithmetic(Op),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Label,
+    Goto(usize),
+    If(usize),
+    Function(u16),
+    Call(String, u16),
+    Return,
+}
+
+/// Executes VM programs directly, without translating them to Hack assembly first.
+///
+/// Calls to functions that aren't defined by the loaded files are dispatched to a
+/// built-in implementation of the Jack OS, so user programs can be run without
+/// the OS classes, or with only some of them.
+///
+/// Memory is laid out as on the Hack platform: the pointers `SP`, `LCL`, `ARG`, `THIS`
+/// and `THAT` live in RAM[0..5], `temp` in RAM[5..13], statics are allocated from 16
+/// upwards and the stack starts at 256. Call frames are built exactly as the ones
+/// generated by [`CodeWriter`](crate::code_writer::CodeWriter), except that return
+/// addresses are command indices instead of ROM addresses.
+///
+/// In checking mode, stack underflows and overflows, segment indices out of range,
+/// `this`/`that` accesses through null pointers and writes to the keyboard register
+/// or beyond are reported as errors, instead of silently corrupting memory.
+#[derive(Debug)]
+pub struct Emulator {
+    ram: Vec<i16>,
+    program: Vec<Instruction>,
+    sites: Vec<Site>,
+    functions: HashMap<String, usize>,
+    statics: HashMap<String, usize>,
+    frames: Vec<Frame>,
+    os: os::Os,
+    pc: usize,
+    halted: bool,
+    returned: bool,
+    checking: bool,
+    vm_extensions: bool,
+    steps: usize,
+    limit: usize,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self {
+            ram: vec![0; RAM_SIZE],
+            program: vec![],
+            sites: vec![],
+            functions: HashMap::new(),
+            statics: HashMap::new(),
+            frames: vec![],
+            os: Default::default(),
+            pc: 0,
+            halted: false,
+            returned: false,
+            checking: false,
+            vm_extensions: false,
+            steps: 0,
+            limit: usize::MAX,
+        }
+    }
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_checking(&mut self, checking: bool) {
+        self.checking = checking;
+    }
+
+    /// Accepts `mul`, `div`, `shl` and `shr` in the files loaded afterwards.
+    pub fn set_vm_extensions(&mut self, enabled: bool) {
+        self.vm_extensions = enabled;
+    }
+
+    /// Loads the commands of a single .vm file, `name` is the file name (e.g. `Main.vm`).
+    pub fn load(&mut self, name: &str, input: &str) -> Result {
+        let commands = parser::parse_with(name, input, self.vm_extensions)?;
+
+        self.load_commands(name, &commands)
+    }
+
+    /// Loads commands that have already been parsed from the file `name`.
+    pub fn load_commands(&mut self, name: &str, commands: &[SourceCommand]) -> Result {
+        let module = name.strip_suffix(".vm").unwrap_or(name);
+        let file: Rc<str> = name.into();
+
+        // labels are scoped to the enclosing function, or to the file
+        // for commands that come before the first function
+        let mut function: Rc<str> = module.into();
+        let mut labels = HashMap::new();
+        let mut jumps = vec![];
+
+        for SourceCommand { command, line } in commands {
+            let pos = self.program.len();
+
+            command.check().map_err(|kind| {
+                vec![VmError::Command {
+                    file: name.to_owned(),
+                    line: *line,
+                    kind,
+                }]
+            })?;
+
+            let instruction = match command {
+                VmCommand::Arithmetic(op) => Instruction::Arithmetic(*op),
+                VmCommand::Push(segment, index) => {
+                    Instruction::Push(*segment, self.resolve(module, *segment, *index))
+                }
+                VmCommand::Pop(segment, index) => {
+                    Instruction::Pop(*segment, self.resolve(module, *segment, *index))
+                }
+                VmCommand::Label(label) => {
+                    labels.insert(format!("{function}${label}"), pos);
+                    Instruction::Label
+                }
+                VmCommand::Goto(label) => {
+                    jumps.push((pos, function.to_string(), label.clone()));
+                    Instruction::Goto(0)
+                }
+                VmCommand::IfGoto(label) => {
+                    jumps.push((pos, function.to_string(), label.clone()));
+                    Instruction::If(0)
+                }
+                VmCommand::Function { name, n_vars } => {
+                    function = name.as_str().into();
+
+                    if self.functions.insert(name.clone(), pos).is_some() {
+                        return Err(Error::DuplicateFunction(name.clone()));
+                    }
+
+                    Instruction::Function(*n_vars)
+                }
+                VmCommand::Call { name, n_args } => Instruction::Call(name.clone(), *n_args),
+                VmCommand::Return => Instruction::Return,
+            };
+
+            self.program.push(instruction);
+            self.sites.push(Site {
+                file: file.clone(),
+                function: function.clone(),
+                line: *line,
+            });
+        }
+
+        for (pos, function, label) in jumps {
+            let target = *labels
+                .get(&format!("{function}${label}"))
+                .ok_or(Error::UndefinedLabel { function, label })?;
+
+            match &mut self.program[pos] {
+                Instruction::Goto(t) | Instruction::If(t) => *t = target,
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the stack pointer and calls `Sys.init`, as the bootstrap code emitted by
+    /// `CodeWriter::write_init` does, if the program defines either `Sys.init` or
+    /// `Main.main` (in which case the built-in `Sys.init` is used). Otherwise execution
+    /// starts at the first loaded command and the other memory segments are left for
+    /// the caller to set up.
+    ///
+    /// Must be called after all the files have been loaded.
+    pub fn bootstrap(&mut self) {
+        self.pc = 0;
+        self.halted = false;
+        self.ram[SP] = STACK;
+
+        if self.functions.contains_key("Sys.init") || self.functions.contains_key("Main.main") {
+            // the call returns past the end of the program, which halts it
+            self.program.push(Instruction::Call("Sys.init".into(), 0));
+            self.sites.push(Site {
+                file: "".into(),
+                function: "bootstrap".into(),
+                line: 0,
+            });
+            self.pc = self.program.len() - 1;
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted || self.pc >= self.program.len()
+    }
+
+    /// Runs until the program halts or `max_steps` commands have been executed,
+    /// returning the number of executed commands.
+    pub fn run(&mut self, max_steps: usize) -> Result<usize> {
+        let start = self.steps;
+        self.limit = start.saturating_add(max_steps);
+
+        while self.steps < self.limit && !self.is_halted() {
+            match self.step() {
+                Err(Error::StepLimit) => break,
+                result => result?,
+            }
+        }
+
+        Ok(self.steps - start)
+    }
+
+    /// Executes a single command.
+    pub fn step(&mut self) -> Result {
+        if self.is_halted() {
+            return Ok(());
+        }
+
+        self.steps += 1;
+
+        match self.execute() {
+            // errors raised by nested calls from built-ins are already located
+            Err(e @ (Error::At(..) | Error::Halted | Error::StepLimit)) => Err(e),
+            Err(e) => {
+                let site = &self.sites[self.pc];
+                let location = Location {
+                    file: site.file.to_string(),
+                    function: site.function.to_string(),
+                    line: site.line,
+                };
+
+                Err(Error::At(location, Box::new(e)))
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn execute(&mut self) -> Result {
+        let mut next = self.pc + 1;
+
+        match self.program[self.pc].clone() {
+            Instruction::Arithmetic(op) => self.arithmetic(op)?,
+            Instruction::Push(segment, index) => {
+                let value = self.read(segment, index)?;
+                self.push(value)?;
+            }
+            Instruction::Pop(segment, index) => {
+                let value = self.pop()?;
+                self.write(segment, index, value)?;
+            }
+            Instruction::Label => (),
+            Instruction::Goto(target) => {
+                // `label L; goto L` is how VM programs halt
+                if target <= self.pc
+                    && self.program[target..self.pc]
+                        .iter()
+                        .all(|i| matches!(i, Instruction::Label))
+                {
+                    self.halted = true;
+                }
+
+                next = target;
+            }
+            Instruction::If(target) => {
+                if self.pop()? != 0 {
+                    next = target;
+                }
+            }
+            Instruction::Function(n_vars) => {
+                match self.frames.last_mut() {
+                    Some(frame) => frame.n_vars = n_vars,
+                    None => self.frames.push(Frame {
+                        n_args: None,
+                        n_vars,
+                    }),
+                }
+
+                for _ in 0..n_vars {
+                    self.push(0)?;
+                }
+            }
+            Instruction::Call(name, n_args) => {
+                if let Some(&entry) = self.functions.get(&name) {
+                    self.push(next as i16)?;
+                    return self.call(entry, n_args);
+                }
+
+                let args = self.pop_args(n_args)?;
+
+                match self.native(&name, &args) {
+                    Err(Error::Halted) => self.halted = true,
+                    value => self.push(value?)?,
+                }
+            }
+            Instruction::Return => return self.r#return(),
+        }
+
+        self.pc = next;
+
+        Ok(())
+    }
+
+    pub fn peek(&self, address: usize) -> i16 {
+        self.ram[address]
+    }
+
+    pub fn poke(&mut self, address: usize, value: i16) {
+        self.ram[address] = value;
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    /// Queues keys to be returned by `Keyboard.readChar`, newlines are read as
+    /// the newline key (128).
+    pub fn type_keys(&mut self, keys: &str) {
+        let keys = keys.chars().map(|c| match c {
+            '\n' => 128,
+            c => c as i16,
+        });
+
+        self.os.input.extend(keys);
+    }
+
+    /// Returns the text printed so far by the built-in `Output` class.
+    pub fn output(&self) -> &str {
+        &self.os.output
+    }
+
+    /// Calls `name` with `args` from a built-in function and runs it to completion.
+    fn invoke(&mut self, name: &str, args: &[i16]) -> Result<i16> {
+        let Some(&entry) = self.functions.get(name) else {
+            return self.native(name, args);
+        };
+
+        let pc = self.pc;
+
+        for &arg in args {
+            self.push(arg)?;
+        }
+
+        self.push(NATIVE)?;
+        self.call(entry, args.len() as u16)?;
+
+        while !mem::take(&mut self.returned) {
+            if self.is_halted() {
+                return Err(Error::Halted);
+            }
+
+            if self.steps >= self.limit {
+                return Err(Error::StepLimit);
+            }
+
+            self.step()?;
+        }
+
+        self.pc = pc;
+        self.pop()
+    }
+
+    fn native(&mut self, name: &str, args: &[i16]) -> Result<i16> {
+        let (n_args, function) =
+            os::lookup(name).ok_or_else(|| Error::UndefinedFunction(name.to_owned()))?;
+
+        if n_args as usize != args.len() {
+            return Err(Error::ArgumentCount {
+                function: name.to_owned(),
+                expected: n_args,
+                found: args.len() as u16,
+            });
+        }
+
+        function(self, args)
+    }
+
+    fn pop_args(&mut self, n_args: u16) -> Result<Vec<i16>> {
+        let mut args = (0..n_args)
+            .map(|_| self.pop())
+            .collect::<Result<Vec<_>>>()?;
+        args.reverse();
+
+        Ok(args)
+    }
+
+    /// Pushes the rest of the frame after the return address and jumps to the callee.
+    fn call(&mut self, entry: usize, n_args: u16) -> Result {
+        // the return address is already on the stack
+        if self.checking && self.depth() < n_args as i32 + 1 {
+            return Err(Error::StackUnderflow);
+        }
+
+        self.frames.push(Frame {
+            n_args: Some(n_args),
+            n_vars: 0,
+        });
+
+        for pointer in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[pointer])?;
+        }
+
+        let sp = self.ram[SP];
+        self.ram[ARG] = sp.wrapping_sub(5).wrapping_sub(n_args as i16);
+        self.ram[LCL] = sp;
+        self.pc = entry;
+
+        Ok(())
+    }
+
+    fn r#return(&mut self) -> Result {
+        let end_frame = self.ram[LCL] as i32;
+        let return_address = self.fetch(end_frame - 5)?;
+
+        let value = self.pop()?;
+        let arg = self.ram[ARG] as i32;
+        self.store(arg, value)?;
+        self.ram[SP] = (arg + 1) as i16;
+
+        self.ram[THAT] = self.fetch(end_frame - 1)?;
+        self.ram[THIS] = self.fetch(end_frame - 2)?;
+        self.ram[ARG] = self.fetch(end_frame - 3)?;
+        self.ram[LCL] = self.fetch(end_frame - 4)?;
+        self.frames.pop();
+
+        if return_address == NATIVE {
+            self.returned = true;
+        } else {
+            // returning anywhere outside the program halts it
+            self.pc = return_address as u16 as usize;
+        }
+
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, op: Op) -> Result {
+        let y = self.pop()?;
+
+        let x = if op.is_unary() { 0 } else { self.pop()? };
+
+        self.push(op.apply(x, y))
+    }
+
+    fn read(&self, segment: Segment, index: u16) -> Result<i16> {
+        match segment {
+            Segment::Constant => Ok(index as i16),
+            _ => self.fetch(self.address(segment, index)?),
+        }
+    }
+
+    fn write(&mut self, segment: Segment, index: u16, value: i16) -> Result {
+        self.store(self.address(segment, index)?, value)
+    }
+
+    /// Number of values on the working stack of the current function.
+    fn depth(&self) -> i32 {
+        let base = match self.frames.last() {
+            Some(frame) => self.ram[LCL] as i32 + frame.n_vars as i32,
+            None => STACK as i32,
+        };
+
+        self.ram[SP] as i32 - base
+    }
+
+    fn check(&self, segment: Segment, index: u16) -> Result {
+        let frame = self.frames.last();
+
+        let (segment, size) = match segment {
+            Segment::Pointer | Segment::Temp => (segment, segment.size()),
+            Segment::Local => (segment, frame.map(|f| f.n_vars)),
+            Segment::Argument => (segment, frame.and_then(|f| f.n_args)),
+            Segment::Static if index >= STACK as u16 => {
+                return Err(Error::SegmentIndex {
+                    segment,
+                    index: index - STATIC as u16,
+                    size: STACK as u16 - STATIC as u16,
+                })
+            }
+            Segment::This if self.ram[THIS] == 0 => return Err(Error::NullPointer(Segment::This)),
+            Segment::That if self.ram[THAT] == 0 => return Err(Error::NullPointer(Segment::That)),
+            _ => return Ok(()),
+        };
+
+        match size {
+            Some(size) if index >= size => Err(Error::SegmentIndex {
+                segment,
+                index,
+                size,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn address(&self, segment: Segment, index: u16) -> Result<i32> {
+        if self.checking {
+            self.check(segment, index)?;
+        }
+
+        let index = index as i32;
+
+        let address = match segment {
+            Segment::Local => self.ram[LCL] as i32 + index,
+            Segment::Argument => self.ram[ARG] as i32 + index,
+            Segment::This => self.ram[THIS] as i32 + index,
+            Segment::That => self.ram[THAT] as i32 + index,
+            Segment::Pointer => (THIS as i32) + index,
+            Segment::Temp => (TEMP as i32) + index,
+            Segment::Static => index,
+            Segment::Constant => unreachable!(),
+        };
+
+        Ok(address)
+    }
+
+    fn push(&mut self, value: i16) -> Result {
+        let sp = self.ram[SP];
+
+        if self.checking && sp >= HEAP {
+            return Err(Error::StackOverflow);
+        }
+        self.store(sp as i32, value)?;
+        self.ram[SP] = sp.wrapping_add(1);
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i16> {
+        if self.checking && self.depth() <= 0 {
+            return Err(Error::StackUnderflow);
+        }
+
+        let sp = self.ram[SP].wrapping_sub(1);
+        self.ram[SP] = sp;
+
+        self.fetch(sp as i32)
+    }
+
+    fn fetch(&self, address: i32) -> Result<i16> {
+        self.ram
+            .get(checked(address)?)
+            .copied()
+            .ok_or(Error::InvalidAddress(address))
+    }
+
+    fn store(&mut self, address: i32, value: i16) -> Result {
+        if self.checking && address >= KBD {
+            return Err(Error::ReadOnly(address));
+        }
+
+        let cell = self
+            .ram
+            .get_mut(checked(address)?)
+            .ok_or(Error::InvalidAddress(address))?;
+
+        *cell = value;
+
+        Ok(())
+    }
+
+    /// Index to store in an instruction: the allocated address for `static`.
+    fn resolve(&mut self, module: &str, segment: Segment, index: u16) -> u16 {
+        match segment {
+            Segment::Static => self.allocate_static(module, index) as u16,
+            _ => index,
+        }
+    }
+
+    /// Allocates `{module}.{index}` the way the assembler does: in order of first
+    /// appearance, starting at RAM[16].
+    fn allocate_static(&mut self, module: &str, index: u16) -> usize {
+        let next = STATIC + self.statics.len();
+
+        *self
+            .statics
+            .entry(format!("{module}.{index}"))
+            .or_insert(next)
+    }
+}
+
+fn checked(address: i32) -> Result<usize> {
+    usize::try_from(address).map_err(|_| Error::InvalidAddress(address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    fn run(files: &[(&str, &str)], presets: &[(usize, i16)]) -> Emulator {
+        let mut emulator = Emulator::new();
+
+        for (name, input) in files {
+            emulator.load(name, input).unwrap();
+        }
+
+        emulator.bootstrap();
+
+        for &(address, value) in presets {
+            emulator.poke(address, value);
+        }
+
+        emulator.run(100_000).unwrap();
+        assert!(emulator.is_halted());
+
+        emulator
+    }
+
+    fn assert_ram(emulator: &Emulator, expected: &[(usize, i16)]) {
+        for &(address, value) in expected {
+            assert_eq!(emulator.peek(address), value, "RAM[{address}]");
+        }
+    }
+
+    #[test]
+    fn stack_test() {
+        let input = include_str!("../../projects/7/StackArithmetic/StackTest/StackTest.vm");
+        let emulator = run(&[("StackTest.vm", input)], &[(SP, 256)]);
+
+        #[rustfmt::skip]
+        assert_ram(&emulator, &[
+            (0, 266), (256, -1), (257, 0), (258, 0), (259, 0), (260, -1),
+            (261, 0), (262, -1), (263, 0), (264, 0), (265, -91),
+        ]);
+    }
+
+    #[test]
+    fn basic_test() {
+        let input = include_str!("../../projects/7/MemoryAccess/BasicTest/BasicTest.vm");
+        let presets = [
+            (SP, 256),
+            (LCL, 300),
+            (ARG, 400),
+            (THIS, 3000),
+            (THAT, 3010),
+        ];
+        let emulator = run(&[("BasicTest.vm", input)], &presets);
+
+        #[rustfmt::skip]
+        assert_ram(&emulator, &[
+            (256, 472), (300, 10), (401, 21), (402, 22),
+            (3006, 36), (3012, 42), (3015, 45), (11, 510),
+        ]);
+    }
+
+    #[test]
+    fn pointer_test() {
+        let input = include_str!("../../projects/7/MemoryAccess/PointerTest/PointerTest.vm");
+        let emulator = run(&[("PointerTest.vm", input)], &[(SP, 256)]);
+
+        #[rustfmt::skip]
+        assert_ram(&emulator, &[(256, 6084), (3, 3030), (4, 3040), (3032, 32), (3046, 46)]);
+    }
+
+    #[test]
+    fn static_test() {
+        let input = include_str!("../../projects/7/MemoryAccess/StaticTest/StaticTest.vm");
+        let emulator = run(&[("StaticTest.vm", input)], &[(SP, 256)]);
+
+        assert_ram(&emulator, &[(256, 1110)]);
+    }
+
+    #[test]
+    fn fibonacci_series() {
+        let input = include_str!("../../projects/8/ProgramFlow/FibonacciSeries/FibonacciSeries.vm");
+        let presets = [(SP, 256), (LCL, 300), (ARG, 400), (400, 6), (401, 3000)];
+        let emulator = run(&[("FibonacciSeries.vm", input)], &presets);
+
+        #[rustfmt::skip]
+        assert_ram(&emulator, &[(3000, 0), (3001, 1), (3002, 1), (3003, 2), (3004, 3), (3005, 5)]);
+    }
+
+    #[test]
+    fn simple_function() {
+        let input = include_str!("../../projects/8/FunctionCalls/SimpleFunction/SimpleFunction.vm");
+
+        #[rustfmt::skip]
+        let presets = [
+            (SP, 317), (LCL, 317), (ARG, 310), (THIS, 3000), (THAT, 4000),
+            (310, 1234), (311, 37), (312, 1000), (313, 305), (314, 300), (315, 3010), (316, 4010),
+        ];
+        let emulator = run(&[("SimpleFunction.vm", input)], &presets);
+
+        #[rustfmt::skip]
+        assert_ram(&emulator, &[(0, 311), (1, 305), (2, 300), (3, 3010), (4, 4010), (310, 1196)]);
+    }
+
+    #[test]
+    fn nested_call() {
+        let input = include_str!("../../projects/8/FunctionCalls/NestedCall/Sys.vm");
+        let emulator = run(&[("Sys.vm", input)], &[]);
+
+        #[rustfmt::skip]
+        assert_ram(&emulator, &[(0, 261), (1, 261), (2, 256), (3, 4000), (4, 5000), (5, 135), (6, 246)]);
+    }
+
+    #[test]
+    fn fibonacci_element() {
+        let main = include_str!("../../projects/8/FunctionCalls/FibonacciElement/Main.vm");
+        let sys = include_str!("../../projects/8/FunctionCalls/FibonacciElement/Sys.vm");
+        let emulator = run(&[("Main.vm", main), ("Sys.vm", sys)], &[]);
+
+        assert_ram(&emulator, &[(0, 262), (261, 3)]);
+    }
+
+    #[test]
+    fn statics_test() {
+        let class1 = include_str!("../../projects/8/FunctionCalls/StaticsTest/Class1.vm");
+        let class2 = include_str!("../../projects/8/FunctionCalls/StaticsTest/Class2.vm");
+        let sys = include_str!("../../projects/8/FunctionCalls/StaticsTest/Sys.vm");
+        let emulator = run(
+            &[
+                ("Class1.vm", class1),
+                ("Class2.vm", class2),
+                ("Sys.vm", sys),
+            ],
+            &[],
+        );
+
+        assert_ram(&emulator, &[(0, 263), (261, -2), (262, 8)]);
+    }
+
+    #[test]
+    fn builtin_os() {
+        let input = include_str!("../../projects/11/Seven/Main.vm");
+        let emulator = run(&[("Main.vm", input)], &[]);
+
+        assert_eq!(emulator.output(), "7");
+    }
+
+    #[test]
+    fn builtin_os_with_vm_classes() {
+        let main = include_str!("../../projects/11/Seven/Main.vm");
+        let math = include_str!("../../tools/OS/Math.vm");
+        let memory = include_str!("../../tools/OS/Memory.vm");
+        let string = include_str!("../../tools/OS/String.vm");
+        let files = [
+            ("Main.vm", main),
+            ("Math.vm", math),
+            ("Memory.vm", memory),
+            ("String.vm", string),
+        ];
+        let emulator = run(&files, &[]);
+
+        assert_eq!(emulator.output(), "7");
+    }
+
+    #[test]
+    fn builtin_keyboard() {
+        let input = include_str!("../../projects/11/Average/Main.vm");
+
+        let mut emulator = Emulator::new();
+        emulator.load("Main.vm", input).unwrap();
+        emulator.type_keys("2\n10\n21\n");
+        emulator.bootstrap();
+        emulator.run(100_000).unwrap();
+
+        assert_eq!(
+            emulator.output(),
+            "How many numbers? 2\nEnter a number: 10\nEnter a number: 21\nThe average is 15"
+        );
+    }
+
+    #[test]
+    fn builtin_memory() {
+        let input = include_str!("../../projects/11/ConvertToBin/Main.vm");
+        let emulator = run(&[("Main.vm", input)], &[(8000, 0b1011)]);
+
+        assert_ram(
+            &emulator,
+            &[(8001, 1), (8002, 1), (8003, 0), (8004, 1), (8005, 0)],
+        );
+    }
+
+    #[test]
+    fn stops_inside_builtin_sys_init() {
+        let input = "function Main.main 0
+label LOOP
+push constant 1
+pop temp 0
+goto LOOP";
+
+        let mut emulator = Emulator::new();
+        emulator.load("Main.vm", input).unwrap();
+        emulator.bootstrap();
+
+        assert_eq!(emulator.run(1000).unwrap(), 1000);
+        assert!(!emulator.is_halted());
+    }
+
+    #[test]
+    fn invalid_free() {
+        let main = |pointer: &str| {
+            format!(
+                "function Main.main 1
+push constant 3
+call Memory.alloc 1
+pop local 0
+{pointer}
+call Memory.deAlloc 1
+pop temp 0
+push local 0
+call Memory.deAlloc 1
+pop temp 0
+push constant 0
+return"
+            )
+        };
+
+        for (pointer, freed) in [("push local 0", 2049), ("push constant 100", 100)] {
+            let mut emulator = Emulator::new();
+            emulator.load("Main.vm", &main(pointer)).unwrap();
+            emulator.bootstrap();
+
+            let Error::At(location, err) = emulator.run(100_000).unwrap_err() else {
+                panic!("{pointer}")
+            };
+
+            assert!(matches!(*err, Error::InvalidFree(a) if a == freed), "{err}");
+            assert_eq!(location.line, if freed == 100 { 6 } else { 9 });
+        }
+    }
+
+    fn run_checked(input: &str) -> Error {
+        let mut emulator = Emulator::new();
+        emulator.set_checking(true);
+        emulator.load("Main.vm", input).unwrap();
+        emulator.bootstrap();
+
+        emulator.run(100_000).unwrap_err()
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let err = run_checked("function Main.main 1\npush local 0\nadd\nreturn");
+
+        let Error::At(location, err) = err else {
+            panic!("{err}")
+        };
+
+        assert!(matches!(*err, Error::StackUnderflow));
+        assert_eq!(location.function, "Main.main");
+        assert_eq!(location.file, "Main.vm");
+        assert_eq!(location.line, 3);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let err = run_checked("function Main.main 0\ncall Main.main 0\nreturn");
+
+        assert!(matches!(err, Error::At(_, e) if matches!(*e, Error::StackOverflow)));
+    }
+
+    #[test]
+    fn segment_bounds() {
+        // fixed-size segments are already checked when loading
+        let err = Emulator::new()
+            .load(
+                "Main.vm",
+                "function Main.main 0\npush constant 1\npop temp 9\nreturn",
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Parse(e)
+            if matches!(e[0].kind(), Some(ErrorKind::IndexOutOfRange { .. }))));
+
+        let err = run_checked("function Main.main 1\npush local 1\nreturn");
+        assert!(matches!(err, Error::At(_, e) if matches!(*e, Error::SegmentIndex { .. })));
+
+        let err = run_checked("function Main.main 0\npush this 0\nreturn");
+        assert!(matches!(err, Error::At(_, e) if matches!(*e, Error::NullPointer(Segment::This))));
+    }
+
+    #[test]
+    fn read_only() {
+        let err = run_checked("function Main.main 0\npush constant 24576\npush constant 1\ncall Memory.poke 2\nreturn");
+
+        assert!(matches!(err, Error::At(_, e) if matches!(*e, Error::ReadOnly(24576))));
+    }
+
+    #[test]
+    fn undefined_function() {
+        let err = run_checked("function Main.main 0\ncall Main.run 0\nreturn");
+
+        assert_eq!(
+            err.to_string(),
+            "function `Main.run` is not defined, in `Main.main` (Main.vm:2)"
+        );
+    }
+
+    #[test]
+    fn undefined_label() {
+        let mut emulator = Emulator::new();
+        let err = emulator.load("Main.vm", "function Main.main 0\ngoto END\nreturn");
+
+        assert!(matches!(err, Err(Error::UndefinedLabel { .. })));
+    }
+}
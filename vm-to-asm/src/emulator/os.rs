@@ -0,0 +1,705 @@
+use std::collections::VecDeque;
+
+use super::{Emulator, Error, Result};
+
+type Native = fn(&mut Emulator, &[i16]) -> Result<i16>;
+
+const HEAP: usize = 2048;
+const SCREEN: usize = 16384;
+const KBD: usize = 24576;
+
+const ROWS: usize = 23;
+const COLUMNS: usize = 64;
+
+const NEWLINE: i16 = 128;
+const BACKSPACE: i16 = 129;
+const DOUBLE_QUOTE: i16 = 34;
+
+/// State kept by the built-in OS classes between calls.
+#[derive(Debug)]
+pub struct Os {
+    /// Free heap blocks as (address, size) pairs, sorted by address.
+    free: Vec<(usize, usize)>,
+    color: bool,
+    row: usize,
+    column: usize,
+    pub input: VecDeque<i16>,
+    pub output: String,
+}
+
+impl Default for Os {
+    fn default() -> Self {
+        Self {
+            free: vec![(HEAP, SCREEN - HEAP)],
+            color: true,
+            row: 0,
+            column: 0,
+            input: VecDeque::new(),
+            output: String::new(),
+        }
+    }
+}
+
+/// Returns the built-in implementation of an OS function and the number of arguments
+/// it takes. Built-ins call other OS classes through [`Emulator::invoke`], so any class
+/// can be replaced by its VM implementation.
+pub fn lookup(name: &str) -> Option<(u16, Native)> {
+    let native: (u16, Native) = match name {
+        "Math.init" => (0, |_, _| Ok(0)),
+        "Math.abs" => (1, |_, a| Ok(a[0].wrapping_abs())),
+        "Math.multiply" => (2, |_, a| Ok(a[0].wrapping_mul(a[1]))),
+        "Math.divide" => (2, math_divide),
+        "Math.min" => (2, |_, a| Ok(a[0].min(a[1]))),
+        "Math.max" => (2, |_, a| Ok(a[0].max(a[1]))),
+        "Math.sqrt" => (1, math_sqrt),
+
+        "Memory.init" => (0, memory_init),
+        "Memory.peek" => (1, |e, a| e.fetch(a[0] as i32)),
+        "Memory.poke" => (2, |e, a| e.store(a[0] as i32, a[1]).map(|_| 0)),
+        "Memory.alloc" => (1, memory_alloc),
+        "Memory.deAlloc" => (1, memory_dealloc),
+
+        "Array.new" => (1, array_new),
+        "Array.dispose" => (1, |e, a| e.invoke("Memory.deAlloc", a)),
+
+        "String.new" => (1, string_new),
+        "String.dispose" => (1, |e, a| e.invoke("Memory.deAlloc", a)),
+        "String.length" => (1, |e, a| e.fetch(a[0] as i32 + 1)),
+        "String.charAt" => (2, string_char_at),
+        "String.setCharAt" => (3, string_set_char_at),
+        "String.appendChar" => (2, string_append_char),
+        "String.eraseLastChar" => (1, string_erase_last_char),
+        "String.intValue" => (1, string_int_value),
+        "String.setInt" => (2, string_set_int),
+        "String.newLine" => (0, |_, _| Ok(NEWLINE)),
+        "String.backSpace" => (0, |_, _| Ok(BACKSPACE)),
+        "String.doubleQuote" => (0, |_, _| Ok(DOUBLE_QUOTE)),
+
+        "Output.init" => (0, output_init),
+        "Output.moveCursor" => (2, output_move_cursor),
+        "Output.printChar" => (1, output_print_char),
+        "Output.printString" => (1, output_print_string),
+        "Output.printInt" => (1, output_print_int),
+        "Output.println" => (0, output_println),
+        "Output.backSpace" => (0, output_back_space),
+
+        "Screen.init" => (0, |e, _| screen_set_color(e, &[-1])),
+        "Screen.clearScreen" => (0, screen_clear),
+        "Screen.setColor" => (1, screen_set_color),
+        "Screen.drawPixel" => (2, screen_draw_pixel),
+        "Screen.drawLine" => (4, screen_draw_line),
+        "Screen.drawRectangle" => (4, screen_draw_rectangle),
+        "Screen.drawCircle" => (3, screen_draw_circle),
+
+        "Keyboard.init" => (0, |_, _| Ok(0)),
+        "Keyboard.keyPressed" => (0, |e, _| e.fetch(KBD as i32)),
+        "Keyboard.readChar" => (0, keyboard_read_char),
+        "Keyboard.readLine" => (1, keyboard_read_line),
+        "Keyboard.readInt" => (1, keyboard_read_int),
+
+        "Sys.init" => (0, sys_init),
+        "Sys.halt" => (0, |_, _| Err(Error::Halted)),
+        "Sys.error" => (1, sys_error),
+        "Sys.wait" => (1, sys_wait),
+
+        _ => return None,
+    };
+
+    Some(native)
+}
+
+/// Reports an OS error through `Sys.error`, which normally halts the program.
+fn error(e: &mut Emulator, code: i16) -> Result<i16> {
+    e.invoke("Sys.error", &[code])
+}
+
+fn math_divide(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if a[1] == 0 {
+        return error(e, 3);
+    }
+
+    Ok(a[0].wrapping_div(a[1]))
+}
+
+fn math_sqrt(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if a[0] < 0 {
+        return error(e, 4);
+    }
+
+    let mut y = 0i32;
+    while (y + 1) * (y + 1) <= a[0] as i32 {
+        y += 1;
+    }
+
+    Ok(y as i16)
+}
+
+fn memory_init(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    e.os.free = Os::default().free;
+
+    Ok(0)
+}
+
+/// First-fit allocation, every block is preceded by a header holding its size.
+fn memory_alloc(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if a[0] <= 0 {
+        return error(e, 5);
+    }
+
+    let size = a[0] as usize + 1;
+
+    let Some(i) = e.os.free.iter().position(|&(_, free)| free >= size) else {
+        return error(e, 6);
+    };
+
+    let (address, free) = e.os.free[i];
+
+    if free == size {
+        e.os.free.remove(i);
+    } else {
+        e.os.free[i] = (address + size, free - size);
+    }
+
+    e.store(address as i32, size as i16)?;
+
+    Ok(address as i16 + 1)
+}
+
+fn memory_dealloc(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let invalid = || Error::InvalidFree(a[0]);
+
+    // the header before the block holds its size, which the program may have overwritten
+    let address = a[0] as i32 - 1;
+    let size = e.fetch(address).map_err(|_| invalid())?;
+
+    let (Ok(address), Ok(size)) = (usize::try_from(address), usize::try_from(size)) else {
+        return Err(invalid());
+    };
+
+    if address < HEAP || size < 2 || address + size > SCREEN {
+        return Err(invalid());
+    }
+
+    let free = &mut e.os.free;
+    let i = free.partition_point(|&(a, _)| a < address);
+
+    // freed twice, or overlapping a free block
+    let after = free.get(i).is_some_and(|&(a, _)| a < address + size);
+    let before = i > 0 && free[i - 1].0 + free[i - 1].1 > address;
+
+    if after || before {
+        return Err(invalid());
+    }
+
+    free.insert(i, (address, size));
+
+    // merge with the following and the preceding blocks
+    if i + 1 < free.len() && address + size == free[i + 1].0 {
+        free[i].1 += free.remove(i + 1).1;
+    }
+
+    if i > 0 && free[i - 1].0 + free[i - 1].1 == address {
+        free[i - 1].1 += free.remove(i).1;
+    }
+
+    Ok(0)
+}
+
+fn array_new(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if a[0] <= 0 {
+        return error(e, 2);
+    }
+
+    e.invoke("Memory.alloc", a)
+}
+
+// A string is laid out as [maxLength, length, chars..].
+
+fn string_new(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if a[0] < 0 {
+        return error(e, 14);
+    }
+
+    let this = e.invoke("Memory.alloc", &[a[0] + 2])?;
+    e.store(this as i32, a[0])?;
+    e.store(this as i32 + 1, 0)?;
+
+    Ok(this)
+}
+
+fn string_char_at(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (this, j) = (a[0] as i32, a[1]);
+
+    if j < 0 || j >= e.fetch(this + 1)? {
+        return error(e, 15);
+    }
+
+    e.fetch(this + 2 + j as i32)
+}
+
+fn string_set_char_at(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (this, j, c) = (a[0] as i32, a[1], a[2]);
+
+    if j < 0 || j >= e.fetch(this + 1)? {
+        return error(e, 16);
+    }
+
+    e.store(this + 2 + j as i32, c)?;
+
+    Ok(0)
+}
+
+fn string_append_char(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (this, c) = (a[0] as i32, a[1]);
+    let len = e.fetch(this + 1)?;
+
+    if len >= e.fetch(this)? {
+        return error(e, 17);
+    }
+
+    e.store(this + 2 + len as i32, c)?;
+    e.store(this + 1, len + 1)?;
+
+    Ok(a[0])
+}
+
+fn string_erase_last_char(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let this = a[0] as i32;
+    let len = e.fetch(this + 1)?;
+
+    if len == 0 {
+        return error(e, 18);
+    }
+
+    e.store(this + 1, len - 1)?;
+
+    Ok(0)
+}
+
+fn string_int_value(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let this = a[0] as i32;
+    let len = e.fetch(this + 1)? as i32;
+
+    let mut i = 0;
+    let negative = len > 0 && e.fetch(this + 2)? == '-' as i16;
+    if negative {
+        i += 1;
+    }
+
+    let mut value = 0i16;
+
+    while i < len {
+        let c = e.fetch(this + 2 + i)?;
+
+        if !(0x30..=0x39).contains(&c) {
+            break;
+        }
+
+        value = value.wrapping_mul(10).wrapping_add(c - 0x30);
+        i += 1;
+    }
+
+    Ok(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn string_set_int(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (this, value) = (a[0] as i32, a[1]);
+    let digits = value.to_string();
+
+    if digits.len() as i16 > e.fetch(this)? {
+        return error(e, 19);
+    }
+
+    for (i, c) in digits.chars().enumerate() {
+        e.store(this + 2 + i as i32, c as i16)?;
+    }
+
+    e.store(this + 1, digits.len() as i16)?;
+
+    Ok(0)
+}
+
+fn output_init(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    e.os.row = 0;
+    e.os.column = 0;
+
+    Ok(0)
+}
+
+fn output_move_cursor(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (row, column) = (a[0], a[1]);
+
+    if !(0..ROWS as i16).contains(&row) || !(0..COLUMNS as i16).contains(&column) {
+        return error(e, 20);
+    }
+
+    e.os.row = row as usize;
+    e.os.column = column as usize;
+    draw_char(e, ' ' as i16)?;
+
+    Ok(0)
+}
+
+fn output_print_char(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    match a[0] {
+        NEWLINE => return output_println(e, a),
+        BACKSPACE => return output_back_space(e, a),
+        c => {
+            draw_char(e, c)?;
+            e.os.output.push(char::from_u32(c as u32).unwrap_or(' '));
+        }
+    }
+
+    if e.os.column == COLUMNS - 1 {
+        output_println(e, &[])?;
+        // wrapping isn't a newline the program asked for
+        e.os.output.pop();
+    } else {
+        e.os.column += 1;
+    }
+
+    Ok(0)
+}
+
+fn output_print_string(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let len = e.invoke("String.length", a)?;
+
+    for i in 0..len {
+        let c = e.invoke("String.charAt", &[a[0], i])?;
+        output_print_char(e, &[c])?;
+    }
+
+    Ok(0)
+}
+
+fn output_print_int(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    for c in a[0].to_string().chars() {
+        output_print_char(e, &[c as i16])?;
+    }
+
+    Ok(0)
+}
+
+fn output_println(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    e.os.row = (e.os.row + 1) % ROWS;
+    e.os.column = 0;
+    e.os.output.push('\n');
+
+    Ok(0)
+}
+
+fn output_back_space(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    if e.os.column > 0 {
+        e.os.column -= 1;
+    } else if e.os.row > 0 {
+        e.os.row -= 1;
+        e.os.column = COLUMNS - 1;
+    }
+
+    draw_char(e, ' ' as i16)?;
+    e.os.output.pop();
+
+    Ok(0)
+}
+
+/// Draws `c` in the 8x11 frame under the cursor, each 16-bit word of the screen
+/// holds two adjacent frames.
+fn draw_char(e: &mut Emulator, c: i16) -> Result {
+    let glyph = match c {
+        32..=126 => &FONT[c as usize - 31],
+        _ => &FONT[0],
+    };
+
+    let mut address = SCREEN + e.os.row * 11 * 32 + e.os.column / 2;
+
+    for &row in glyph {
+        let word = e.fetch(address as i32)?;
+
+        let word = if e.os.column & 1 == 0 {
+            (word & !0xff) | row as i16
+        } else {
+            (word & 0xff) | ((row as i16) << 8)
+        };
+
+        e.store(address as i32, word)?;
+        address += 32;
+    }
+
+    Ok(())
+}
+
+fn screen_clear(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    e.ram[SCREEN..KBD].fill(0);
+
+    Ok(0)
+}
+
+fn screen_set_color(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    e.os.color = a[0] != 0;
+
+    Ok(0)
+}
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..512).contains(&x) && (0..256).contains(&y)
+}
+
+fn set_pixel(e: &mut Emulator, x: i16, y: i16) {
+    let address = SCREEN + 32 * y as usize + x as usize / 16;
+    let mask = 1 << (x % 16);
+
+    if e.os.color {
+        e.ram[address] |= mask;
+    } else {
+        e.ram[address] &= !mask;
+    }
+}
+
+fn screen_draw_pixel(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if !on_screen(a[0], a[1]) {
+        return error(e, 7);
+    }
+
+    set_pixel(e, a[0], a[1]);
+
+    Ok(0)
+}
+
+fn screen_draw_line(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (x1, y1, x2, y2) = (a[0], a[1], a[2], a[3]);
+
+    if !on_screen(x1, y1) || !on_screen(x2, y2) {
+        return error(e, 8);
+    }
+
+    // Bresenham's algorithm, for all octants
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let (mut x, mut y, mut diff) = (x1, y1, dx + dy);
+
+    loop {
+        set_pixel(e, x, y);
+
+        if x == x2 && y == y2 {
+            break;
+        }
+
+        if 2 * diff >= dy {
+            diff += dy;
+            x += sx;
+        }
+
+        if 2 * diff <= dx {
+            diff += dx;
+            y += sy;
+        }
+    }
+
+    Ok(0)
+}
+
+fn screen_draw_rectangle(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (x1, y1, x2, y2) = (a[0], a[1], a[2], a[3]);
+
+    if !on_screen(x1, y1) || !on_screen(x2, y2) || x1 > x2 || y1 > y2 {
+        return error(e, 9);
+    }
+
+    for y in y1..=y2 {
+        for x in x1..=x2 {
+            set_pixel(e, x, y);
+        }
+    }
+
+    Ok(0)
+}
+
+fn screen_draw_circle(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let (x, y, r) = (a[0], a[1], a[2]);
+
+    if !on_screen(x, y) {
+        return error(e, 12);
+    }
+
+    if !(0..=181).contains(&r) {
+        return error(e, 13);
+    }
+
+    for dy in -r..=r {
+        let dx = math_sqrt(e, &[r * r - dy * dy])?;
+
+        for px in (x - dx)..=(x + dx) {
+            if on_screen(px, y + dy) {
+                set_pixel(e, px, y + dy);
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+fn keyboard_read_char(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    let key = e.os.input.pop_front().ok_or(Error::EndOfInput)?;
+    e.invoke("Output.printChar", &[key])?;
+
+    Ok(key)
+}
+
+fn keyboard_read_line(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    e.invoke("Output.printString", a)?;
+    let line = e.invoke("String.new", &[80])?;
+
+    loop {
+        match e.invoke("Keyboard.readChar", &[])? {
+            NEWLINE => return Ok(line),
+            BACKSPACE => {
+                if e.invoke("String.length", &[line])? > 0 {
+                    e.invoke("String.eraseLastChar", &[line])?;
+                }
+            }
+            c => {
+                e.invoke("String.appendChar", &[line, c])?;
+            }
+        }
+    }
+}
+
+fn keyboard_read_int(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    let line = e.invoke("Keyboard.readLine", a)?;
+    let value = e.invoke("String.intValue", &[line])?;
+    e.invoke("String.dispose", &[line])?;
+
+    Ok(value)
+}
+
+fn sys_init(e: &mut Emulator, _: &[i16]) -> Result<i16> {
+    for class in ["Memory", "Math", "Screen", "Output", "Keyboard"] {
+        e.invoke(&format!("{class}.init"), &[])?;
+    }
+
+    e.invoke("Main.main", &[])?;
+    e.invoke("Sys.halt", &[])
+}
+
+fn sys_error(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    for c in "ERR".chars() {
+        e.invoke("Output.printChar", &[c as i16])?;
+    }
+
+    e.invoke("Output.printInt", a)?;
+    e.invoke("Sys.halt", &[])
+}
+
+fn sys_wait(e: &mut Emulator, a: &[i16]) -> Result<i16> {
+    if a[0] < 0 {
+        return error(e, 1);
+    }
+
+    Ok(0)
+}
+
+/// Bitmaps of the characters 32..=126, preceded by the black square displayed
+/// for the non-printable ones. Each row holds 8 pixels, least significant first.
+#[rustfmt::skip]
+const FONT: [[u8; 11]; 96] = [
+    [63, 63, 63, 63, 63, 63, 63, 63, 63, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [12, 30, 30, 30, 12, 12, 0, 12, 12, 0, 0],
+    [54, 54, 20, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 18, 18, 63, 18, 18, 63, 18, 18, 0, 0],
+    [12, 30, 51, 3, 30, 48, 51, 30, 12, 12, 0],
+    [0, 0, 35, 51, 24, 12, 6, 51, 49, 0, 0],
+    [12, 30, 30, 12, 54, 27, 27, 27, 54, 0, 0],
+    [12, 12, 6, 0, 0, 0, 0, 0, 0, 0, 0],
+    [24, 12, 6, 6, 6, 6, 6, 12, 24, 0, 0],
+    [6, 12, 24, 24, 24, 24, 24, 12, 6, 0, 0],
+    [0, 0, 0, 51, 30, 63, 30, 51, 0, 0, 0],
+    [0, 0, 0, 12, 12, 63, 12, 12, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 12, 12, 6, 0],
+    [0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 12, 12, 0, 0],
+    [0, 0, 32, 48, 24, 12, 6, 3, 1, 0, 0],
+    [12, 30, 51, 51, 51, 51, 51, 30, 12, 0, 0],
+    [12, 14, 15, 12, 12, 12, 12, 12, 63, 0, 0],
+    [30, 51, 48, 24, 12, 6, 3, 51, 63, 0, 0],
+    [30, 51, 48, 48, 28, 48, 48, 51, 30, 0, 0],
+    [16, 24, 28, 26, 25, 63, 24, 24, 60, 0, 0],
+    [63, 3, 3, 31, 48, 48, 48, 51, 30, 0, 0],
+    [28, 6, 3, 3, 31, 51, 51, 51, 30, 0, 0],
+    [63, 49, 48, 48, 24, 12, 12, 12, 12, 0, 0],
+    [30, 51, 51, 51, 30, 51, 51, 51, 30, 0, 0],
+    [30, 51, 51, 51, 62, 48, 48, 24, 14, 0, 0],
+    [0, 0, 12, 12, 0, 0, 12, 12, 0, 0, 0],
+    [0, 0, 12, 12, 0, 0, 12, 12, 6, 0, 0],
+    [0, 0, 24, 12, 6, 3, 6, 12, 24, 0, 0],
+    [0, 0, 0, 63, 0, 0, 63, 0, 0, 0, 0],
+    [0, 0, 3, 6, 12, 24, 12, 6, 3, 0, 0],
+    [30, 51, 51, 24, 12, 12, 0, 12, 12, 0, 0],
+    [30, 51, 51, 59, 59, 59, 27, 3, 30, 0, 0],
+    [12, 30, 51, 51, 63, 51, 51, 51, 51, 0, 0],
+    [31, 51, 51, 51, 31, 51, 51, 51, 31, 0, 0],
+    [28, 54, 35, 3, 3, 3, 35, 54, 28, 0, 0],
+    [15, 27, 51, 51, 51, 51, 51, 27, 15, 0, 0],
+    [63, 51, 35, 11, 15, 11, 35, 51, 63, 0, 0],
+    [63, 51, 35, 11, 15, 11, 3, 3, 3, 0, 0],
+    [28, 54, 35, 3, 59, 51, 51, 54, 44, 0, 0],
+    [51, 51, 51, 51, 63, 51, 51, 51, 51, 0, 0],
+    [30, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0],
+    [60, 24, 24, 24, 24, 24, 27, 27, 14, 0, 0],
+    [51, 51, 51, 27, 15, 27, 51, 51, 51, 0, 0],
+    [3, 3, 3, 3, 3, 3, 35, 51, 63, 0, 0],
+    [33, 51, 63, 63, 51, 51, 51, 51, 51, 0, 0],
+    [51, 51, 55, 55, 63, 59, 59, 51, 51, 0, 0],
+    [30, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0],
+    [31, 51, 51, 51, 31, 3, 3, 3, 3, 0, 0],
+    [30, 51, 51, 51, 51, 51, 63, 59, 30, 48, 0],
+    [31, 51, 51, 51, 31, 27, 51, 51, 51, 0, 0],
+    [30, 51, 51, 6, 28, 48, 51, 51, 30, 0, 0],
+    [63, 63, 45, 12, 12, 12, 12, 12, 30, 0, 0],
+    [51, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0],
+    [51, 51, 51, 51, 51, 30, 30, 12, 12, 0, 0],
+    [51, 51, 51, 51, 51, 63, 63, 63, 18, 0, 0],
+    [51, 51, 30, 30, 12, 30, 30, 51, 51, 0, 0],
+    [51, 51, 51, 51, 30, 12, 12, 12, 30, 0, 0],
+    [63, 51, 49, 24, 12, 6, 35, 51, 63, 0, 0],
+    [30, 6, 6, 6, 6, 6, 6, 6, 30, 0, 0],
+    [0, 0, 1, 3, 6, 12, 24, 48, 32, 0, 0],
+    [30, 24, 24, 24, 24, 24, 24, 24, 30, 0, 0],
+    [8, 28, 54, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 63, 0],
+    [6, 12, 24, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 14, 24, 30, 27, 27, 54, 0, 0],
+    [3, 3, 3, 15, 27, 51, 51, 51, 30, 0, 0],
+    [0, 0, 0, 30, 51, 3, 3, 51, 30, 0, 0],
+    [48, 48, 48, 60, 54, 51, 51, 51, 30, 0, 0],
+    [0, 0, 0, 30, 51, 63, 3, 51, 30, 0, 0],
+    [28, 54, 38, 6, 15, 6, 6, 6, 15, 0, 0],
+    [0, 0, 30, 51, 51, 51, 62, 48, 51, 30, 0],
+    [3, 3, 3, 27, 55, 51, 51, 51, 51, 0, 0],
+    [12, 12, 0, 14, 12, 12, 12, 12, 30, 0, 0],
+    [48, 48, 0, 56, 48, 48, 48, 48, 51, 30, 0],
+    [3, 3, 3, 51, 27, 15, 15, 27, 51, 0, 0],
+    [14, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0],
+    [0, 0, 0, 29, 63, 43, 43, 43, 43, 0, 0],
+    [0, 0, 0, 29, 51, 51, 51, 51, 51, 0, 0],
+    [0, 0, 0, 30, 51, 51, 51, 51, 30, 0, 0],
+    [0, 0, 0, 30, 51, 51, 51, 31, 3, 3, 0],
+    [0, 0, 0, 30, 51, 51, 51, 62, 48, 48, 0],
+    [0, 0, 0, 29, 55, 51, 3, 3, 7, 0, 0],
+    [0, 0, 0, 30, 51, 6, 24, 51, 30, 0, 0],
+    [4, 6, 6, 15, 6, 6, 6, 54, 28, 0, 0],
+    [0, 0, 0, 27, 27, 27, 27, 27, 54, 0, 0],
+    [0, 0, 0, 51, 51, 51, 51, 30, 12, 0, 0],
+    [0, 0, 0, 51, 51, 51, 63, 63, 18, 0, 0],
+    [0, 0, 0, 51, 30, 12, 12, 30, 51, 0, 0],
+    [0, 0, 0, 51, 51, 51, 62, 48, 24, 15, 0],
+    [0, 0, 0, 63, 27, 12, 6, 51, 63, 0, 0],
+    [56, 12, 12, 12, 7, 12, 12, 12, 56, 0, 0],
+    [12, 12, 12, 12, 12, 12, 12, 12, 12, 0, 0],
+    [7, 12, 12, 12, 56, 12, 12, 12, 7, 0, 0],
+    [38, 45, 25, 0, 0, 0, 0, 0, 0, 0, 0],
+];
@@ -0,0 +1,103 @@
+use std::{error, fmt, io};
+
+use crate::statics::MAX_STATICS;
+
+pub type Result<T = ()> = std::result::Result<T, VmError>;
+
+/// An error found while translating a VM file.
+#[derive(Debug)]
+pub enum VmError {
+    Io(io::Error),
+    /// An invalid command, at its line in the original source file.
+    Command {
+        file: String,
+        line: usize,
+        kind: ErrorKind,
+    },
+    /// More static variables in the program than the static segment holds.
+    TooManyStatics(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownCommand(String),
+    /// A command that extends the VM language, which wasn't enabled.
+    VmExtension(String),
+    MissingArgument(&'static str),
+    UnexpectedArgument(String),
+    InvalidNumber(String),
+    InvalidSegment(String),
+    IndexOutOfRange {
+        segment: String,
+        index: u16,
+    },
+    DuplicateLabel {
+        function: String,
+        label: String,
+    },
+    UndefinedLabel {
+        function: String,
+        label: String,
+    },
+}
+
+impl VmError {
+    pub fn kind(&self) -> Option<&ErrorKind> {
+        match self {
+            VmError::Command { kind, .. } => Some(kind),
+            VmError::Io(_) | VmError::TooManyStatics(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::Io(e) => write!(f, "{e}"),
+            VmError::Command { file, line, kind } => write!(f, "{file}:{line}: {kind}"),
+            VmError::TooManyStatics(count) => write!(
+                f,
+                "the program uses {count} static variables, but only {MAX_STATICS} fit in RAM 16 to 255"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ErrorKind::*;
+
+        match self {
+            UnknownCommand(c) => write!(f, "unknown command `{c}`"),
+            VmExtension(c) => write!(f, "`{c}` is a VM extension, enable it with --vm-extensions"),
+            MissingArgument(a) => write!(f, "missing {a}"),
+            UnexpectedArgument(a) => write!(f, "unexpected argument `{a}`"),
+            InvalidNumber(n) => write!(f, "`{n}` is not a number between 0 and 32767"),
+            InvalidSegment(s) => write!(f, "invalid segment `{s}`"),
+            IndexOutOfRange { segment, index } => {
+                write!(f, "index {index} is out of range for segment `{segment}`")
+            }
+            DuplicateLabel { function, label } => {
+                write!(f, "label `{label}` is already defined in `{function}`")
+            }
+            UndefinedLabel { function, label } => {
+                write!(f, "label `{label}` is not defined in `{function}`")
+            }
+        }
+    }
+}
+
+impl error::Error for VmError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            VmError::Io(e) => Some(e),
+            VmError::Command { .. } | VmError::TooManyStatics(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for VmError {
+    fn from(e: io::Error) -> Self {
+        VmError::Io(e)
+    }
+}
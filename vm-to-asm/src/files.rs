@@ -0,0 +1,28 @@
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+/// Expands directories into the .vm files they contain, sorted by name.
+pub fn expand(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries = fs::read_dir(input)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>>>()?;
+
+            entries.retain(|p| is_vm_file(p));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(input.clone());
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_vm_file(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|e| e == "vm")
+}
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+use crate::command::{Segment, SourceCommand, VmCommand};
+
+/// The functions that can be called with a slim frame, which doesn't save THIS and
+/// THAT: those that never set `pointer`.
+///
+/// Their callees restore THIS and THAT themselves, or never change them either, so
+/// a call leaves them as they were. This includes leaf functions like `Math.abs`, and
+/// any function that only works on its arguments and locals.
+pub fn slim_functions<'a>(
+    commands: impl IntoIterator<Item = &'a SourceCommand>,
+) -> HashSet<String> {
+    let mut slim = HashSet::new();
+    let mut sets_pointer = HashSet::new();
+
+    let mut function = None;
+
+    for SourceCommand { command, .. } in commands {
+        match command {
+            VmCommand::Function { name, .. } => {
+                slim.insert(name.clone());
+                function = Some(name);
+            }
+            VmCommand::Pop(Segment::Pointer, _) => {
+                if let Some(name) = function {
+                    sets_pointer.insert(name.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    slim.retain(|name| !sets_pointer.contains(name));
+    slim
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn finds_functions_that_keep_this_and_that() {
+        let input = "function Math.abs 0
+push argument 0
+return
+function Ball.move 0
+push argument 0
+pop pointer 0
+push this 0
+call Math.abs 1
+return
+function Main.main 0
+call Ball.move 1
+return";
+
+        let commands = parser::parse("Main.vm", input).unwrap();
+        let mut slim: Vec<_> = slim_functions(&commands).into_iter().collect();
+        slim.sort();
+
+        assert_eq!(slim, ["Main.main", "Math.abs"]);
+    }
+}
@@ -1,38 +1,303 @@
 // use std::error::Error;
 // use std::io::Write;
 
-use std::fmt;
-
 pub mod code_writer;
+pub mod command;
+#[cfg(test)]
+mod cpu;
+pub mod disassembler;
+pub mod emulator;
+pub mod error;
+pub mod files;
+pub mod frames;
+pub mod lint;
+pub mod optimizer;
 pub mod parser;
+pub mod statics;
+
+use code_writer::CodeWriter;
+use command::{SourceCommand, VmCommand};
+use error::VmError;
+
+/// Translates .vm files, given as file names and contents, into a Hack assembly
+/// program, returning every error found in them.
+///
+/// The program starts with the bootstrap code when one of the files defines `Sys.init`.
+pub fn translate(inputs: &[(&str, &str)]) -> Result<String, Vec<VmError>> {
+    translate_with(inputs, false)
+}
+
+/// Translates .vm files like `translate`, accepting the VM extensions if
+/// `vm_extensions` is set.
+pub fn translate_with(
+    inputs: &[(&str, &str)],
+    vm_extensions: bool,
+) -> Result<String, Vec<VmError>> {
+    let modules = parser::parse_files_with(inputs, vm_extensions)?;
+    statics::check(&statics::usage(&modules)).map_err(|e| vec![e])?;
+
+    let mut asm = vec![];
+    let mut code_writer = CodeWriter::with_output(&mut asm);
+
+    if modules
+        .iter()
+        .any(|(_, commands)| defines_sys_init(commands))
+    {
+        code_writer.write_init().map_err(|e| vec![e])?;
+    }
+
+    for (file, commands) in &modules {
+        code_writer.set_module_name(file);
+        code_writer.write_commands(commands).map_err(|e| vec![e])?;
+    }
+
+    code_writer.close().map_err(|e| vec![e])?;
 
-#[derive(Debug)]
-pub enum CommandType {
-    Arithmetic,
-    Push,
-    Pop,
-    Label,
-    Goto,
-    If,
-    Function,
-    Return,
-    Call,
+    Ok(String::from_utf8(asm).expect("assembly is ascii"))
 }
 
-impl fmt::Display for CommandType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use CommandType::*;
-
-        match self {
-            Arithmetic => todo!(),
-            Push => write!(f, "push"),
-            Pop => write!(f, "pop"),
-            Label => todo!(),
-            Goto => todo!(),
-            If => todo!(),
-            Function => todo!(),
-            Return => todo!(),
-            Call => todo!(),
+/// Whether `commands` define `Sys.init`, the function the bootstrap code calls.
+pub fn defines_sys_init(commands: &[SourceCommand]) -> bool {
+    commands
+        .iter()
+        .any(|c| matches!(&c.command, VmCommand::Function { name, .. } if name == "Sys.init"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiler::compilation::{Analyzer, VMWriter};
+
+    fn compile(jack: &str) -> String {
+        compile_with(jack, false)
+    }
+
+    fn compile_with(jack: &str, vm_extensions: bool) -> String {
+        let tokens = compiler::tokenize::tokenize(jack)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let ast = compiler::parse::parse(tokens).unwrap();
+
+        let mut vm = vec![];
+        let mut vm_writer = VMWriter::new(&mut vm);
+        vm_writer.set_vm_extensions(vm_extensions);
+        vm_writer.analyze(&ast).unwrap();
+
+        String::from_utf8(vm).unwrap()
+    }
+
+    #[test]
+    fn runs_jack_programs() {
+        // the whole OS doesn't fit in ROM without shared routines
+        let sys = compile(
+            "class Sys {
+                function void init() {
+                    do Memory.init();
+                    do Math.init();
+                    do Main.main();
+                    while (true) {}
+                    return;
+                }
+
+                function void error(int code) {
+                    while (true) {}
+                    return;
+                }
+            }",
+        );
+        let main = compile(include_str!("../../projects/11/ConvertToBin/Main.jack"));
+        let array = include_str!("../../tools/OS/Array.vm");
+        let math = include_str!("../../tools/OS/Math.vm");
+        let memory = include_str!("../../tools/OS/Memory.vm");
+
+        let inputs = [
+            ("Main.vm", &*main),
+            ("Array.vm", array),
+            ("Math.vm", math),
+            ("Memory.vm", memory),
+            ("Sys.vm", &*sys),
+        ];
+
+        let asm = translate(&inputs).unwrap();
+        let mut cpu = crate::cpu::assemble(&asm);
+        cpu.ram[8000] = 12345;
+        cpu.run(100_000);
+
+        let bits: Vec<_> = (0..16).map(|i| (12345 >> i) & 1).collect();
+        assert_eq!(cpu.ram[8001..8017], bits);
+    }
+
+    #[test]
+    fn runs_unqualified_calls() {
+        let sys = compile(
+            "class Sys {
+                function void init() {
+                    do Memory.init();
+                    do Main.main();
+                    while (true) {}
+                    return;
+                }
+            }",
+        );
+        let main = compile(
+            "class Main {
+                field int x;
+
+                constructor Main new(int v) {
+                    let x = v;
+                    do bump();
+                    return this;
+                }
+
+                method void bump() {
+                    let x = twice(x);
+                    return;
+                }
+
+                method int get() {
+                    return x;
+                }
+
+                function int twice(int n) {
+                    return n + n;
+                }
+
+                function void main() {
+                    var Array out;
+                    var Main m;
+                    let out = 8000;
+                    let out[0] = twice(21);
+                    let m = Main.new(5);
+                    let out[1] = m.get();
+                    let out[2] = twice(twice(3));
+                    return;
+                }
+            }",
+        );
+
+        let inputs = [
+            ("Main.vm", &*main),
+            ("Array.vm", include_str!("../../tools/OS/Array.vm")),
+            ("Math.vm", include_str!("../../tools/OS/Math.vm")),
+            ("Memory.vm", include_str!("../../tools/OS/Memory.vm")),
+            ("Sys.vm", &*sys),
+        ];
+
+        let asm = translate(&inputs).unwrap();
+        let mut cpu = crate::cpu::assemble(&asm);
+        cpu.run(100_000);
+
+        assert_eq!(cpu.ram[8000..8003], [42, 10, 12]);
+    }
+
+    #[test]
+    fn slim_frames_run_math_test_faster() {
+        let sys = compile(
+            "class Sys {
+                function void init() {
+                    do Memory.init();
+                    do Math.init();
+                    do Main.main();
+                    do Memory.poke(8100, 1);
+                    while (true) {}
+                    return;
+                }
+
+                function void error(int code) {
+                    while (true) {}
+                    return;
+                }
+            }",
+        );
+        let main = compile(include_str!("../../projects/12/MathTest/Main.jack"));
+        let math = compile(include_str!("../../projects/12/Math.jack"));
+
+        let inputs = [
+            ("Main.vm", &*main),
+            ("Array.vm", include_str!("../../tools/OS/Array.vm")),
+            ("Math.vm", &*math),
+            ("Memory.vm", include_str!("../../tools/OS/Memory.vm")),
+            ("Sys.vm", &*sys),
+        ];
+        let modules = parser::parse_files(&inputs).unwrap();
+
+        let mut steps = vec![];
+
+        for slim in [false, true] {
+            let mut asm = vec![];
+            let mut code_writer = CodeWriter::with_output(&mut asm);
+
+            if slim {
+                let commands = modules.iter().flat_map(|(_, commands)| commands);
+                code_writer.set_slim_frames(frames::slim_functions(commands));
+            }
+
+            code_writer.write_init().unwrap();
+            for (file, commands) in &modules {
+                code_writer.set_module_name(file);
+                code_writer.write_commands(commands).unwrap();
+            }
+            code_writer.close().unwrap();
+
+            let mut cpu = crate::cpu::assemble(std::str::from_utf8(&asm).unwrap());
+            steps.push(cpu.run_until(10_000_000, |cpu| cpu.ram[8100] == 1).unwrap());
+
+            assert_eq!(
+                cpu.ram[8000..8014],
+                [6, -180, -18000, -18000, 0, 3, -3000, 0, 3, 181, 123, 123, 27, 32767]
+            );
         }
+
+        assert!(steps[1] < steps[0], "{steps:?}");
+    }
+
+    #[test]
+    fn vm_extensions_run_math_test_faster() {
+        let sys = compile(
+            "class Sys {
+                function void init() {
+                    do Memory.init();
+                    do Math.init();
+                    do Main.main();
+                    do Memory.poke(8100, 1);
+                    while (true) {}
+                    return;
+                }
+
+                function void error(int code) {
+                    while (true) {}
+                    return;
+                }
+            }",
+        );
+
+        let mut steps = vec![];
+
+        for vm_extensions in [false, true] {
+            let main = compile_with(
+                include_str!("../../projects/12/MathTest/Main.jack"),
+                vm_extensions,
+            );
+            let math = compile_with(include_str!("../../projects/12/Math.jack"), vm_extensions);
+
+            let inputs = [
+                ("Main.vm", &*main),
+                ("Array.vm", include_str!("../../tools/OS/Array.vm")),
+                ("Math.vm", &*math),
+                ("Memory.vm", include_str!("../../tools/OS/Memory.vm")),
+                ("Sys.vm", &*sys),
+            ];
+
+            let asm = translate_with(&inputs, vm_extensions).unwrap();
+            let mut cpu = crate::cpu::assemble(&asm);
+            steps.push(cpu.run_until(10_000_000, |cpu| cpu.ram[8100] == 1).unwrap());
+
+            assert_eq!(
+                cpu.ram[8000..8014],
+                [6, -180, -18000, -18000, 0, 3, -3000, 0, 3, 181, 123, 123, 27, 32767]
+            );
+        }
+
+        assert!(steps[1] < steps[0], "{steps:?}");
     }
 }
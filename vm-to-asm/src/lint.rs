@@ -0,0 +1,442 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::command::{Segment, SourceCommand, VmCommand};
+
+/// A likely mistake in a .vm file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub file: String,
+    pub line: usize,
+    pub kind: LintKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    /// A `local` index past the locals the function declares.
+    LocalOutOfRange {
+        function: String,
+        index: u16,
+        n_vars: u16,
+    },
+    /// More locals declared than the function uses.
+    UnusedLocals {
+        function: String,
+        n_vars: u16,
+        used: u16,
+    },
+    /// A call with fewer arguments than the function uses.
+    TooFewArguments {
+        function: String,
+        n_args: u16,
+        used: u16,
+    },
+    /// A call with more arguments than the function uses.
+    TooManyArguments {
+        function: String,
+        n_args: u16,
+        used: u16,
+    },
+    /// A `return` with nothing on the stack to return.
+    ReturnWithoutValue { function: String },
+    /// A command that takes more values than the function pushed.
+    StackUnderflow {
+        function: String,
+        command: String,
+        needed: usize,
+        depth: usize,
+    },
+    /// A label reached with different numbers of values on the stack.
+    UnbalancedStack {
+        function: String,
+        label: String,
+        depths: (usize, usize),
+    },
+}
+
+impl LintKind {
+    /// Whether the code surely goes wrong, rather than only looking suspicious.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            LintKind::UnusedLocals { .. } | LintKind::TooManyArguments { .. }
+        )
+    }
+}
+
+/// What the commands of a function use.
+struct Function<'a> {
+    file: &'a str,
+    line: usize,
+    name: &'a str,
+    n_vars: u16,
+    commands: &'a [SourceCommand],
+    /// Number of locals and arguments used, one more than the highest index.
+    locals: u16,
+    arguments: u16,
+}
+
+/// Checks the functions of a program, given as parsed .vm files, for:
+///
+/// - locals used but not declared, or declared but not used
+/// - calls with a different number of arguments than the function uses
+/// - commands that pop more values than the function pushed, including `return`
+/// - labels reached with a different stack depth from different branches
+///
+/// The stack depth is only followed within a function, assuming every call pushes
+/// its result. Calls to functions that aren't part of the program aren't checked.
+pub fn lint(modules: &[(&str, Vec<SourceCommand>)]) -> Vec<Lint> {
+    let functions: Vec<_> = modules
+        .iter()
+        .flat_map(|(file, commands)| functions(file, commands))
+        .collect();
+
+    let by_name: HashMap<_, _> = functions.iter().map(|f| (f.name, f)).collect();
+
+    let mut lints = vec![];
+
+    for function in &functions {
+        let lint = |line, kind| Lint {
+            file: function.file.to_owned(),
+            line,
+            kind,
+        };
+
+        for c in function.commands {
+            match c.command {
+                VmCommand::Push(Segment::Local, index) | VmCommand::Pop(Segment::Local, index)
+                    if index >= function.n_vars =>
+                {
+                    lints.push(lint(
+                        c.line,
+                        LintKind::LocalOutOfRange {
+                            function: function.name.to_owned(),
+                            index,
+                            n_vars: function.n_vars,
+                        },
+                    ));
+                }
+                VmCommand::Call { ref name, n_args } => {
+                    let Some(callee) = by_name.get(name.as_str()) else {
+                        continue;
+                    };
+
+                    let (function, used) = (name.clone(), callee.arguments);
+
+                    if n_args < used {
+                        lints.push(lint(
+                            c.line,
+                            LintKind::TooFewArguments {
+                                function,
+                                n_args,
+                                used,
+                            },
+                        ));
+                    } else if n_args > used {
+                        lints.push(lint(
+                            c.line,
+                            LintKind::TooManyArguments {
+                                function,
+                                n_args,
+                                used,
+                            },
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if function.n_vars > function.locals {
+            lints.push(lint(
+                function.line,
+                LintKind::UnusedLocals {
+                    function: function.name.to_owned(),
+                    n_vars: function.n_vars,
+                    used: function.locals,
+                },
+            ));
+        }
+
+        lints.extend(
+            check_stack(function)
+                .into_iter()
+                .map(|(line, kind)| lint(line, kind)),
+        );
+    }
+
+    lints.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    lints
+}
+
+/// The functions defined in the commands of `file`.
+fn functions<'a>(file: &'a str, commands: &'a [SourceCommand]) -> Vec<Function<'a>> {
+    let starts: Vec<_> = commands
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| matches!(c.command, VmCommand::Function { .. }))
+        .map(|(i, _)| i)
+        .chain([commands.len()])
+        .collect();
+
+    starts
+        .windows(2)
+        .map(|range| {
+            let declaration = &commands[range[0]];
+            let VmCommand::Function { ref name, n_vars } = declaration.command else {
+                unreachable!("functions start with `function`");
+            };
+
+            let body = &commands[range[0] + 1..range[1]];
+            let used = |segment| {
+                body.iter()
+                    .filter_map(|c| match c.command {
+                        VmCommand::Push(s, index) | VmCommand::Pop(s, index) if s == segment => {
+                            Some(index + 1)
+                        }
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0)
+            };
+
+            Function {
+                file,
+                line: declaration.line,
+                name,
+                n_vars,
+                commands: body,
+                locals: used(Segment::Local),
+                arguments: used(Segment::Argument),
+            }
+        })
+        .collect()
+}
+
+/// Follows the number of values on the stack through every branch of a function,
+/// returning the problems found with their lines.
+fn check_stack(function: &Function) -> Vec<(usize, LintKind)> {
+    use VmCommand::*;
+
+    let commands = function.commands;
+    let name = || function.name.to_owned();
+
+    let labels: HashMap<_, _> = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| match &c.command {
+            Label(label) => Some((label.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut depths: Vec<Option<usize>> = vec![None; commands.len()];
+    let mut pending = vec![(0, 0)];
+    let mut lints = vec![];
+    let mut unbalanced = BTreeSet::new();
+
+    while let Some((i, depth)) = pending.pop() {
+        let Some(command) = commands.get(i) else {
+            continue;
+        };
+
+        match depths[i] {
+            Some(d) if d == depth => continue,
+            Some(d) => {
+                if let Label(label) = &command.command {
+                    if unbalanced.insert(i) {
+                        let kind = LintKind::UnbalancedStack {
+                            function: name(),
+                            label: label.clone(),
+                            depths: (d.min(depth), d.max(depth)),
+                        };
+                        lints.push((command.line, kind));
+                    }
+                }
+
+                continue;
+            }
+            None => depths[i] = Some(depth),
+        }
+
+        let (pops, pushes) = match &command.command {
+            Arithmetic(op) if op.is_unary() => (1, 1),
+            Arithmetic(_) => (2, 1),
+            Push(..) => (0, 1),
+            Pop(..) | IfGoto(_) | Return => (1, 0),
+            Call { n_args, .. } => (*n_args as usize, 1),
+            Label(_) | Goto(_) | Function { .. } => (0, 0),
+        };
+
+        if depth < pops {
+            let kind = match command.command {
+                Return => LintKind::ReturnWithoutValue { function: name() },
+                _ => LintKind::StackUnderflow {
+                    function: name(),
+                    command: command.command.to_string(),
+                    needed: pops,
+                    depth,
+                },
+            };
+
+            lints.push((command.line, kind));
+            continue;
+        }
+
+        let depth = depth - pops + pushes;
+
+        match &command.command {
+            Goto(label) => pending.extend(labels.get(label.as_str()).map(|&j| (j, depth))),
+            IfGoto(label) => {
+                pending.extend(labels.get(label.as_str()).map(|&j| (j, depth)));
+                pending.push((i + 1, depth));
+            }
+            Return => {}
+            _ => pending.push((i + 1, depth)),
+        }
+    }
+
+    lints
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = if self.kind.is_error() {
+            "error"
+        } else {
+            "warning"
+        };
+
+        write!(f, "{}:{}: {severity}: {}", self.file, self.line, self.kind)
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use LintKind::*;
+
+        match self {
+            LocalOutOfRange {
+                function,
+                index,
+                n_vars,
+            } => write!(
+                f,
+                "`local {index}` is out of range, `{function}` declares {n_vars} locals"
+            ),
+            UnusedLocals {
+                function,
+                n_vars,
+                used,
+            } => write!(f, "`{function}` declares {n_vars} locals but uses {used}"),
+            TooFewArguments {
+                function,
+                n_args,
+                used,
+            } => write!(
+                f,
+                "`{function}` is called with {n_args} arguments but uses {used}"
+            ),
+            TooManyArguments {
+                function,
+                n_args,
+                used,
+            } => write!(
+                f,
+                "`{function}` is called with {n_args} arguments but only uses {used}"
+            ),
+            ReturnWithoutValue { function } => {
+                write!(f, "`{function}` returns without pushing a value")
+            }
+            StackUnderflow {
+                function,
+                command,
+                needed,
+                depth,
+            } => write!(
+                f,
+                "`{command}` takes {needed} values but the stack of `{function}` holds {depth}"
+            ),
+            UnbalancedStack {
+                function,
+                label,
+                depths: (a, b),
+            } => write!(
+                f,
+                "the stack of `{function}` holds {a} values at label `{label}` from one branch, {b} from another"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn lint_program(files: &[(&str, &str)]) -> Vec<String> {
+        let modules = parser::parse_files(files).unwrap();
+        lint(&modules).iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn checks_locals_and_arguments() {
+        let main = "function Main.main 2
+push local 2
+call Math.max 1
+push constant 4
+call Math.abs 2
+return
+function Main.unused 3
+push local 0
+return";
+        let math = "function Math.max 0
+push argument 1
+return
+function Math.abs 0
+push argument 0
+return";
+
+        assert_eq!(
+            lint_program(&[("Main.vm", main), ("Math.vm", math)]),
+            [
+                "Main.vm:2: error: `local 2` is out of range, `Main.main` declares 2 locals",
+                "Main.vm:3: error: `Math.max` is called with 1 arguments but uses 2",
+                "Main.vm:5: warning: `Math.abs` is called with 2 arguments but only uses 1",
+                "Main.vm:7: warning: `Main.unused` declares 3 locals but uses 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_stack_depth() {
+        let main = "function Main.main 0
+push constant 1
+if-goto ELSE
+push constant 2
+push constant 3
+goto END
+label ELSE
+push constant 4
+label END
+return
+function Main.pop 0
+pop temp 0
+push constant 0
+return
+function Main.void 0
+label LOOP
+push constant 0
+if-goto LOOP
+return";
+
+        assert_eq!(
+            lint_program(&[("Main.vm", main)]),
+            [
+                "Main.vm:9: error: the stack of `Main.main` holds 1 values at label `END` from one branch, 2 from another",
+                "Main.vm:12: error: `pop temp 0` takes 1 values but the stack of `Main.pop` holds 0",
+                "Main.vm:19: error: `Main.void` returns without pushing a value",
+            ]
+        );
+    }
+}
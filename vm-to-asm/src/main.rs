@@ -1,75 +1,295 @@
-use std::path::Path;
-use std::{env, fs, io, process};
-use vm_to_asm::{code_writer::CodeWriter, parser::Parser, CommandType};
+use std::path::{Path, PathBuf};
+use std::{env, fs, mem, process};
+use vm_to_asm::command::SourceCommand;
+use vm_to_asm::error::VmError;
+use vm_to_asm::optimizer::{self, Removed};
+use vm_to_asm::statics::{self, StaticUsage, MAX_STATICS};
+use vm_to_asm::{code_writer::CodeWriter, files, frames, parser};
 
-fn main() -> io::Result<()> {
-    let args = env::args().skip(1).collect::<Vec<_>>();
+const USAGE: &str =
+    "help: vm-to-asm [--bootstrap | --no-bootstrap] [--optimize] [--gc-functions] [--shared] [--fast-compare] [--slim-frames] [--no-fuse] [--source-map] [--size-report] [--statics] [--vm-extensions] \
+<input directory | input vm file..> [output asm file]";
 
-    if args.is_empty() {
-        println!("help: vm-to-asm <input vm file..> [output asm file]");
+#[derive(Debug, Default)]
+struct Options {
+    /// Unset to bootstrap only when `Sys.init` is defined.
+    bootstrap: Option<bool>,
+    /// Runs the optimizer and reports what it removed.
+    optimize: bool,
+    /// Removes the functions `Sys.init` never calls, directly or not.
+    gc_functions: bool,
+    /// Calls shared routines for `call`, `return` and comparisons.
+    shared: bool,
+    /// Compares by subtracting, which overflows for operands of different signs.
+    fast_compare: bool,
+    /// Calls functions that never set `pointer` without saving THIS and THAT.
+    slim_frames: bool,
+    /// Translates commands one by one, without superinstructions.
+    no_fuse: bool,
+    /// Comments the output with the vm commands and writes their ROM addresses to a
+    /// `.map` file next to it.
+    source_map: bool,
+    /// Prints the size of the program translated in both modes.
+    size_report: bool,
+    /// Prints the number of static variables of each class.
+    statics: bool,
+    /// Accepts `mul`, `div`, `shl` and `shr`.
+    vm_extensions: bool,
+}
+
+fn main() {
+    let mut vm_files = vec![];
+    let mut asm_file = None;
+    let mut options = Options::default();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--bootstrap" => options.bootstrap = Some(true),
+            "--no-bootstrap" => options.bootstrap = Some(false),
+            "--optimize" => options.optimize = true,
+            "--gc-functions" => options.gc_functions = true,
+            "--shared" => options.shared = true,
+            "--fast-compare" => options.fast_compare = true,
+            "--slim-frames" => options.slim_frames = true,
+            "--no-fuse" => options.no_fuse = true,
+            "--source-map" => options.source_map = true,
+            "--size-report" => options.size_report = true,
+            "--statics" => options.statics = true,
+            "--vm-extensions" => options.vm_extensions = true,
+            _ if arg.ends_with(".asm") => asm_file = Some(PathBuf::from(arg)),
+            _ => vm_files.push(PathBuf::from(arg)),
+        }
+    }
+
+    if vm_files.is_empty() {
+        println!("{USAGE}");
         process::exit(1);
     }
 
-    let (vm_files, asm_file): (Vec<_>, Vec<_>) = args.into_iter().partition(|f| f.ends_with(".vm"));
+    let dest = asm_file.unwrap_or_else(|| default_dest(&vm_files[0]));
 
-    let dest = asm_file.first().expect("must provide an output asm file");
+    let vm_files = match files::expand(&vm_files) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) => {
+            eprintln!("error: no .vm files to translate");
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            process::exit(1);
+        }
+    };
 
-    let mut code_writer = CodeWriter::new();
-    code_writer.set_file_name(dest);
+    if let Err(errors) = translate(&vm_files, &dest, &options) {
+        for e in &errors {
+            eprintln!("error: {e}");
+        }
 
-    if vm_files.len() > 1 {
-        code_writer.write_init()?;
+        eprintln!("translation failed with {} error(s)", errors.len());
+        process::exit(1);
     }
+}
+
+/// `DIR/DIR.asm` for a directory and `Prog.asm` next to `Prog.vm` for a file.
+fn default_dest(input: &Path) -> PathBuf {
+    if input.is_dir() {
+        let name = input.canonicalize().unwrap_or_else(|_| input.to_owned());
+        let name = name.file_name().unwrap_or_default();
+
+        input.join(format!("{}.asm", name.to_string_lossy()))
+    } else {
+        input.with_extension("asm")
+    }
+}
+
+/// Translates the vm files into `dest`, returning every error found in the files.
+///
+/// The bootstrap code is written if `bootstrap` is set, or when it's not specified
+/// and one of the files defines `Sys.init`.
+fn translate(vm_files: &[PathBuf], dest: &Path, options: &Options) -> Result<(), Vec<VmError>> {
+    let mut sources = vec![];
 
     for file in vm_files {
-        let input = fs::read_to_string(&file)?;
-        let mut parser = Parser::new(&input);
+        let input = fs::read_to_string(file).map_err(|e| vec![e.into()])?;
+        let file_name = file.file_name().unwrap().to_str().unwrap().to_owned();
 
-        let file_name = Path::new(&file).file_name().unwrap().to_str().unwrap();
-        code_writer.set_module_name(file_name);
+        sources.push((file_name, input));
+    }
+
+    let inputs: Vec<_> = sources
+        .iter()
+        .map(|(file_name, input)| (file_name.as_str(), input.as_str()))
+        .collect();
+    let mut modules = parser::parse_files_with(&inputs, options.vm_extensions)?;
+
+    if options.optimize {
+        let mut total = 0;
+
+        for (_, commands) in &mut modules {
+            let (optimized, removed) = optimizer::optimize(mem::take(commands));
+            *commands = optimized;
+
+            for Removed { function, count } in removed {
+                println!("{function}: removed {count} commands");
+                total += count;
+            }
+        }
+
+        println!("optimizer removed {total} commands");
+    }
 
-        while parser.has_more_commands() {
-            let command_type = parser.command_type();
-
-            use CommandType::*;
-            match command_type {
-                Arithmetic => {
-                    let command = parser.arg1();
-                    code_writer.write_arithmetic(command)?;
-                }
-                Push | Pop => {
-                    let segment = parser.arg1();
-                    let index = parser.arg2();
-                    code_writer.write_pushpop(command_type, segment, index)?;
-                }
-                Label => {
-                    let label = parser.arg1();
-                    code_writer.write_label(label)?;
-                }
-                If => {
-                    let label = parser.arg1();
-                    code_writer.write_if(label)?;
-                }
-                Goto => {
-                    let label = parser.arg1();
-                    code_writer.write_goto(label)?;
-                }
-                Function => {
-                    let function_name = parser.arg1();
-                    let n_vars = parser.arg2();
-                    code_writer.write_function(function_name, n_vars)?;
-                }
-                Call => {
-                    let function_name = parser.arg1();
-                    let n_args = parser.arg2();
-                    code_writer.write_call(function_name, n_args)?;
-                }
-                Return => code_writer.write_return()?,
+    if options.gc_functions {
+        if modules
+            .iter()
+            .any(|(_, commands)| vm_to_asm::defines_sys_init(commands))
+        {
+            let removed = optimizer::remove_dead_functions(&mut modules, "Sys.init");
+
+            for function in &removed {
+                println!("removed unused function {function}");
             }
 
-            parser.advance();
+            println!("removed {} unused functions", removed.len());
+        } else {
+            println!("kept every function: the program doesn't define Sys.init");
         }
     }
 
+    let usage = statics::usage(&modules);
+
+    if options.statics {
+        print_statics(&usage);
+    }
+
+    statics::check(&usage).map_err(|e| vec![e])?;
+
+    let bootstrap = options.bootstrap.unwrap_or_else(|| {
+        modules
+            .iter()
+            .any(|(_, commands)| vm_to_asm::defines_sys_init(commands))
+    });
+
+    if options.size_report {
+        // the other mode is written first, and overwritten by the selected one
+        write(&modules, dest, bootstrap, !options.shared, options).map_err(|e| vec![e])?;
+        let other = count_instructions(dest).map_err(|e| vec![e])?;
+
+        write(&modules, dest, bootstrap, options.shared, options).map_err(|e| vec![e])?;
+        let selected = count_instructions(dest).map_err(|e| vec![e])?;
+
+        let (inline, shared) = if options.shared {
+            (other, selected)
+        } else {
+            (selected, other)
+        };
+
+        println!("inline: {inline:>6} instructions");
+        println!(
+            "shared: {shared:>6} instructions ({:+.1}%)",
+            percent(shared, inline)
+        );
+
+        return Ok(());
+    }
+
+    write(&modules, dest, bootstrap, options.shared, options).map_err(|e| vec![e])
+}
+
+fn write(
+    modules: &[(&str, Vec<SourceCommand>)],
+    dest: &Path,
+    bootstrap: bool,
+    shared: bool,
+    options: &Options,
+) -> Result<(), VmError> {
+    let mut code_writer = CodeWriter::new();
+    code_writer.set_file_name(dest.to_str().unwrap())?;
+    code_writer.set_shared_routines(shared);
+    code_writer.set_fast_comparisons(options.fast_compare);
+    code_writer.set_superinstructions(!options.no_fuse);
+    code_writer.set_source_map(options.source_map);
+
+    if options.slim_frames {
+        let commands = modules.iter().flat_map(|(_, commands)| commands);
+        code_writer.set_slim_frames(frames::slim_functions(commands));
+    }
+
+    if bootstrap {
+        code_writer.write_init()?;
+    }
+
+    for (file_name, commands) in modules {
+        code_writer.set_module_name(file_name);
+        code_writer.write_commands(commands)?;
+    }
+
+    code_writer.close()?;
+
+    if options.source_map {
+        let map: String = code_writer
+            .source_map()
+            .iter()
+            .map(|entry| format!("{entry}\n"))
+            .collect();
+
+        fs::write(dest.with_extension("map"), map)?;
+    }
+
     Ok(())
 }
+
+/// Number of instructions the assembler will put in ROM.
+fn count_instructions(asm_file: &Path) -> Result<usize, VmError> {
+    let asm = fs::read_to_string(asm_file)?;
+
+    let count = asm
+        .lines()
+        .map(|l| l[..l.find("//").unwrap_or(l.len())].trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('('))
+        .count();
+
+    Ok(count)
+}
+
+fn print_statics(usage: &[StaticUsage]) {
+    let width = usage
+        .iter()
+        .map(|u| u.class.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+
+    println!("{:width$}  statics", "class");
+    for StaticUsage { class, count } in usage {
+        println!("{class:width$}  {count:>7}");
+    }
+
+    let total: usize = usage.iter().map(|u| u.count).sum();
+    println!("{:width$}  {total:>7} / {MAX_STATICS}", "total");
+}
+
+fn percent(value: usize, base: usize) -> f64 {
+    (value as f64 - base as f64) * 100.0 / base.max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn default_dest_of_a_dotted_directory() {
+        let dir = env::temp_dir().join(format!("vm-to-asm-{}.Fbb", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let dest = default_dest(&dir);
+        fs::remove_dir(&dir).unwrap();
+
+        assert_eq!(
+            dest,
+            dir.join(format!("vm-to-asm-{}.Fbb.asm", process::id()))
+        );
+        assert_eq!(default_dest(Path::new("Prog.vm")), Path::new("Prog.asm"));
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use crate::command::{Op, Segment, SourceCommand, VmCommand};
+
+/// Number of commands the optimizer removed from a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removed {
+    pub function: String,
+    pub count: usize,
+}
+
+/// Optimizes the commands of a single .vm file:
+///
+/// - folds arithmetic on constants, and `if-goto` on a constant into a `goto` or nothing
+/// - cancels `not; not` and `neg; neg`, and merges comparisons followed by `not` into
+///   the opposite comparison (`lt; not` becomes `ge`)
+/// - removes `push x; pop x`
+/// - removes code after `goto` and `return` that no label makes reachable, and gotos
+///   to the next command
+///
+/// Returns the functions that got smaller, in order.
+pub fn optimize(commands: Vec<SourceCommand>) -> (Vec<SourceCommand>, Vec<Removed>) {
+    let mut optimized = vec![];
+    let mut removed = vec![];
+
+    for function in split_functions(commands) {
+        let name = match &function[0].command {
+            VmCommand::Function { name, .. } => name.clone(),
+            _ => "(top level)".to_owned(),
+        };
+
+        let before = function.len();
+        let function = optimize_function(function);
+
+        if function.len() < before {
+            let count = before - function.len();
+            removed.push(Removed {
+                function: name,
+                count,
+            });
+        }
+
+        optimized.extend(function);
+    }
+
+    (optimized, removed)
+}
+
+/// Removes the functions that are never called, directly or not, from `root` or
+/// from the commands that come before the first function of a file.
+///
+/// Returns the names of the removed functions, in order.
+pub fn remove_dead_functions<N>(
+    modules: &mut [(N, Vec<SourceCommand>)],
+    root: &str,
+) -> Vec<String> {
+    let mut calls: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut reachable: Vec<&str> = vec![root];
+
+    for (_, commands) in modules.iter() {
+        let mut function = None;
+
+        for SourceCommand { command, .. } in commands {
+            match command {
+                VmCommand::Function { name, .. } => function = Some(name.as_str()),
+                VmCommand::Call { name, .. } => match function {
+                    Some(function) => calls.entry(function).or_default().push(name),
+                    None => reachable.push(name),
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let mut live: HashSet<String> = HashSet::new();
+
+    while let Some(function) = reachable.pop() {
+        if live.insert(function.to_owned()) {
+            reachable.extend(calls.get(function).into_iter().flatten());
+        }
+    }
+
+    let mut removed = vec![];
+
+    for (_, commands) in modules {
+        let functions = split_functions(mem::take(commands));
+
+        for function in functions {
+            match &function[0].command {
+                VmCommand::Function { name, .. } if !live.contains(name) => {
+                    removed.push(name.clone())
+                }
+                _ => commands.extend(function),
+            }
+        }
+    }
+
+    removed
+}
+
+fn split_functions(commands: Vec<SourceCommand>) -> Vec<Vec<SourceCommand>> {
+    let mut functions: Vec<Vec<SourceCommand>> = vec![];
+
+    for command in commands {
+        match functions.last_mut() {
+            Some(function) if !matches!(command.command, VmCommand::Function { .. }) => {
+                function.push(command)
+            }
+            _ => functions.push(vec![command]),
+        }
+    }
+
+    functions
+}
+
+fn optimize_function(mut commands: Vec<SourceCommand>) -> Vec<SourceCommand> {
+    // every rewrite removes at least one command, so this terminates
+    loop {
+        let before = commands.len();
+        commands = remove_unreachable(peephole(commands));
+
+        if commands.len() == before {
+            return commands;
+        }
+    }
+}
+
+fn peephole(commands: Vec<SourceCommand>) -> Vec<SourceCommand> {
+    let mut out = vec![];
+
+    for command in commands {
+        out.push(command);
+        while rewrite(&mut out) {}
+    }
+
+    out
+}
+
+/// Rewrites the commands at the end of `out`, returning whether anything changed.
+fn rewrite(out: &mut Vec<SourceCommand>) -> bool {
+    use VmCommand::*;
+
+    let n = out.len();
+
+    if n >= 2 {
+        match (&out[n - 2].command, &out[n - 1].command) {
+            (Arithmetic(a), Arithmetic(b)) if a == b && a.is_unary() => {
+                out.truncate(n - 2);
+                return true;
+            }
+            (Arithmetic(op), Arithmetic(Op::Not)) if op.is_comparison() => {
+                let negated = op.negated().unwrap();
+                out.pop();
+                out[n - 2].command = Arithmetic(negated);
+                return true;
+            }
+            (Push(a, i), Pop(b, j)) if a == b && i == j => {
+                out.truncate(n - 2);
+                return true;
+            }
+            _ => {}
+        }
+    }
+
+    match out.last().map(|c| c.command.clone()) {
+        Some(IfGoto(label)) => {
+            let Some((value, len)) = constant(&out[..n - 1]) else {
+                return false;
+            };
+
+            let start = n - 1 - len;
+            let line = out[start].line;
+            out.truncate(start);
+
+            if value != 0 {
+                out.push(SourceCommand {
+                    command: Goto(label),
+                    line,
+                });
+            }
+
+            true
+        }
+        Some(Arithmetic(op)) if op.is_unary() => {
+            let Some((y, len)) = constant(&out[..n - 1]) else {
+                return false;
+            };
+
+            let folded = constant_commands(op.apply(0, y));
+            if folded.len() > len {
+                return false;
+            }
+
+            replace(out, n - 1 - len, folded);
+            true
+        }
+        Some(Arithmetic(op)) => {
+            let Some((y, y_len)) = constant(&out[..n - 1]) else {
+                return false;
+            };
+            let Some((x, x_len)) = constant(&out[..n - 1 - y_len]) else {
+                return false;
+            };
+
+            replace(
+                out,
+                n - 1 - y_len - x_len,
+                constant_commands(op.apply(x, y)),
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The value pushed by the commands at the end of `commands`, and how many there are:
+/// `push constant c`, optionally followed by `neg` or `not`.
+fn constant(commands: &[SourceCommand]) -> Option<(i16, usize)> {
+    use VmCommand::*;
+
+    if let Push(Segment::Constant, c) = commands.last()?.command {
+        return Some((c as i16, 1));
+    }
+
+    let [.., push, last] = commands else {
+        return None;
+    };
+
+    match (&push.command, &last.command) {
+        (Push(Segment::Constant, c), Arithmetic(op)) if op.is_unary() => {
+            Some((op.apply(0, *c as i16), 2))
+        }
+        _ => None,
+    }
+}
+
+/// The shortest commands that push `value`.
+fn constant_commands(value: i16) -> Vec<VmCommand> {
+    if value >= 0 {
+        vec![VmCommand::Push(Segment::Constant, value as u16)]
+    } else {
+        vec![
+            VmCommand::Push(Segment::Constant, !value as u16),
+            VmCommand::Arithmetic(Op::Not),
+        ]
+    }
+}
+
+/// Replaces the commands from `start` on, keeping the line of the first one.
+fn replace(out: &mut Vec<SourceCommand>, start: usize, commands: Vec<VmCommand>) {
+    let line = out[start].line;
+    out.truncate(start);
+    out.extend(
+        commands
+            .into_iter()
+            .map(|command| SourceCommand { command, line }),
+    );
+}
+
+fn remove_unreachable(commands: Vec<SourceCommand>) -> Vec<SourceCommand> {
+    let targets: HashSet<String> = commands
+        .iter()
+        .filter_map(|c| match &c.command {
+            VmCommand::Goto(label) | VmCommand::IfGoto(label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let mut out: Vec<SourceCommand> = vec![];
+    let mut reachable = true;
+
+    for command in commands {
+        match &command.command {
+            VmCommand::Function { .. } => reachable = true,
+            VmCommand::Label(label) if targets.contains(label) => {
+                reachable = true;
+
+                // `goto L; label L`
+                if matches!(out.last(), Some(c) if c.command == VmCommand::Goto(label.clone())) {
+                    out.pop();
+                }
+            }
+            _ => {}
+        }
+
+        if !reachable {
+            continue;
+        }
+
+        if matches!(command.command, VmCommand::Goto(_) | VmCommand::Return) {
+            reachable = false;
+        }
+
+        out.push(command);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::parser;
+
+    fn optimized(input: &str) -> (String, Vec<Removed>) {
+        let (commands, removed) = optimize(parser::parse("Main.vm", input).unwrap());
+
+        let output = commands
+            .iter()
+            .map(|c| c.command.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        (output, removed)
+    }
+
+    #[test]
+    fn folds_constants() {
+        let input = "function Main.main 0
+push constant 2
+push constant 3
+add
+push constant 7
+sub
+label LOOP
+push constant 0
+not
+not
+if-goto END
+push constant 0
+not
+if-goto LOOP
+label END
+return";
+
+        let (output, removed) = optimized(input);
+
+        assert_eq!(
+            output,
+            "function Main.main 0
+push constant 1
+not
+label LOOP
+goto LOOP"
+        );
+        assert_eq!(
+            removed,
+            [Removed {
+                function: "Main.main".into(),
+                count: 11
+            }]
+        );
+    }
+
+    #[test]
+    fn inverts_branches() {
+        let input = "function Main.main 0
+push argument 0
+push argument 1
+lt
+not
+if-goto ELSE
+push local 0
+pop local 0
+push constant 1
+return
+push constant 2
+return
+label ELSE
+push constant 3
+return";
+
+        let (output, _) = optimized(input);
+
+        assert_eq!(
+            output,
+            "function Main.main 0
+push argument 0
+push argument 1
+ge
+if-goto ELSE
+push constant 1
+return
+label ELSE
+push constant 3
+return"
+        );
+    }
+
+    #[test]
+    fn removes_dead_functions() {
+        let main = "function Main.main 0
+call Math.abs 1
+return
+function Main.unused 0
+call Math.max 2
+return";
+        let math = "call Math.init 0
+function Math.init 0
+return
+function Math.abs 0
+call Math.abs 1
+return
+function Math.max 0
+return
+function Math.min 0
+return";
+
+        let mut modules = [
+            ("Main.vm", parser::parse("Main.vm", main).unwrap()),
+            ("Math.vm", parser::parse("Math.vm", math).unwrap()),
+        ];
+
+        let removed = remove_dead_functions(&mut modules, "Main.main");
+
+        assert_eq!(removed, ["Main.unused", "Math.max", "Math.min"]);
+        assert_eq!(modules[0].1.len(), 3);
+        assert_eq!(
+            modules[1].1.iter().map(|c| c.line).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn preserves_behavior() {
+        let convert_to_bin = include_str!("../../projects/11/ConvertToBin/Main.vm");
+        let complex_arrays = include_str!("../../projects/11/ComplexArrays/Main.vm");
+
+        for input in [convert_to_bin, complex_arrays] {
+            let mut results = vec![];
+
+            for optimizing in [false, true] {
+                let mut commands = parser::parse("Main.vm", input).unwrap();
+                if optimizing {
+                    commands = optimize(commands).0;
+                }
+
+                let mut emulator = Emulator::new();
+                emulator.load_commands("Main.vm", &commands).unwrap();
+                emulator.poke(8000, 12345);
+                emulator.bootstrap();
+                emulator.run(10_000_000).unwrap();
+
+                let memory = (8001..8017).map(|a| emulator.peek(a)).collect::<Vec<_>>();
+                results.push((memory, emulator.output().to_owned()));
+            }
+
+            assert_eq!(results[0], results[1]);
+        }
+    }
+}
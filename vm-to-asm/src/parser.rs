@@ -1,79 +1,311 @@
-use crate::CommandType;
+use std::collections::HashSet;
 
-#[derive(Debug)]
-pub struct Parser {
-    source: Vec<String>,
-    line: usize,
+use crate::command::{Op, SourceCommand, VmCommand};
+use crate::error::{ErrorKind, VmError};
+
+/// Parses the commands of the .vm file `file`, returning every error found in it.
+pub fn parse(file: &str, input: &str) -> Result<Vec<SourceCommand>, Vec<VmError>> {
+    parse_with(file, input, false)
 }
 
-impl Parser {
-    pub fn new(input: &str) -> Self {
-        let source = input
-            .lines()
-            .map(|l| {
-                let offset = l.find("//").unwrap_or(l.len());
-                let line = l[0..offset].trim();
-                line
-            })
-            .filter(|&l| !l.is_empty())
-            .map(ToOwned::to_owned)
-            .collect();
-
-        Parser { source, line: 0 }
+/// Parses the commands of `file` like `parse`, also accepting `mul`, `div`, `shl`
+/// and `shr` if `vm_extensions` is set.
+pub fn parse_with(
+    file: &str,
+    input: &str,
+    vm_extensions: bool,
+) -> Result<Vec<SourceCommand>, Vec<VmError>> {
+    let mut commands = vec![];
+    let mut errors = vec![];
+
+    for (n, line) in input.lines().enumerate() {
+        let offset = line.find("//").unwrap_or(line.len());
+        let line = line[0..offset].trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_command(line, vm_extensions).and_then(|c| c.check().map(|_| c)) {
+            Ok(command) => commands.push(SourceCommand {
+                command,
+                line: n + 1,
+            }),
+            Err(kind) => errors.push(VmError::Command {
+                file: file.to_owned(),
+                line: n + 1,
+                kind,
+            }),
+        }
     }
 
-    pub fn advance(&mut self) {
-        self.line += 1;
+    if errors.is_empty() {
+        Ok(commands)
+    } else {
+        Err(errors)
     }
+}
 
-    pub fn has_more_commands(&self) -> bool {
-        self.line < self.source.len()
+/// Parses several .vm files, given as file names and contents, and checks their
+/// labels, returning every error found in them.
+pub fn parse_files<'a>(
+    inputs: &[(&'a str, &str)],
+) -> Result<Vec<(&'a str, Vec<SourceCommand>)>, Vec<VmError>> {
+    parse_files_with(inputs, false)
+}
+
+/// Parses several .vm files like `parse_files`, also accepting the commands
+/// `parse_with` accepts if `vm_extensions` is set.
+pub fn parse_files_with<'a>(
+    inputs: &[(&'a str, &str)],
+    vm_extensions: bool,
+) -> Result<Vec<(&'a str, Vec<SourceCommand>)>, Vec<VmError>> {
+    let mut modules = vec![];
+    let mut errors = vec![];
+
+    for &(file, input) in inputs {
+        match parse_with(file, input, vm_extensions) {
+            Ok(commands) => {
+                if let Err(e) = check_labels(file, &commands) {
+                    errors.extend(e);
+                }
+
+                modules.push((file, commands));
+            }
+            Err(e) => errors.extend(e),
+        }
     }
 
-    pub fn command_type(&self) -> CommandType {
-        let line = &self.source[self.line];
-        let mut words = line.split_whitespace();
-
-        use CommandType::*;
-        match words.next().unwrap() {
-            "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" => Arithmetic,
-            "push" => Push,
-            "pop" => Pop,
-            "label" => Label,
-            "goto" => Goto,
-            "if-goto" => If,
-            "function" => Function,
-            "call" => Call,
-            "return" => Return,
-            c => panic!("invalid command {} at line {}", c, self.line + 1),
+    if errors.is_empty() {
+        Ok(modules)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that the labels of every function in the commands of `file` are defined
+/// once, and that its `goto`s and `if-goto`s jump to one of them.
+///
+/// Labels are scoped to the function they're in, commands before the first function
+/// are scoped to the file.
+pub fn check_labels(file: &str, commands: &[SourceCommand]) -> Result<(), Vec<VmError>> {
+    let mut errors = vec![];
+    let error = |line, kind| VmError::Command {
+        file: file.to_owned(),
+        line,
+        kind,
+    };
+
+    let module = file.strip_suffix(".vm").unwrap_or(file);
+
+    let mut functions = vec![(module, vec![])];
+    for command in commands {
+        match &command.command {
+            VmCommand::Function { name, .. } => functions.push((name, vec![command])),
+            _ => functions.last_mut().unwrap().1.push(command),
         }
     }
 
-    pub fn arg1(&self) -> &str {
-        let line = &self.source[self.line];
-        let mut words = line.split_whitespace();
+    for (function, commands) in functions {
+        let mut labels = HashSet::new();
 
-        let cmd = words.next().unwrap();
+        for SourceCommand { command, line } in &commands {
+            if let VmCommand::Label(label) = command {
+                if !labels.insert(label) {
+                    let kind = ErrorKind::DuplicateLabel {
+                        function: function.to_owned(),
+                        label: label.to_owned(),
+                    };
+                    errors.push(error(*line, kind));
+                }
+            }
+        }
 
-        match cmd {
-            "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" => cmd,
-            "pop" | "push" | "label" | "goto" | "if-goto" | "function" | "call" => {
-                words.next().unwrap()
+        for SourceCommand { command, line } in &commands {
+            if let VmCommand::Goto(label) | VmCommand::IfGoto(label) = command {
+                if !labels.contains(label) {
+                    let kind = ErrorKind::UndefinedLabel {
+                        function: function.to_owned(),
+                        label: label.to_owned(),
+                    };
+                    errors.push(error(*line, kind));
+                }
             }
-            _ => panic!("invalid arg1() call at line: {}", line),
         }
     }
 
-    pub fn arg2(&self) -> u16 {
-        let line = &self.source[self.line];
-        let mut words = line.split_whitespace();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        errors.sort_by_key(|e| match e {
+            VmError::Command { line, .. } => *line,
+            _ => 0,
+        });
+        Err(errors)
+    }
+}
+
+fn parse_command(line: &str, vm_extensions: bool) -> Result<VmCommand, ErrorKind> {
+    let mut words = line.split_whitespace();
+
+    let mut argument = |name| words.next().ok_or(ErrorKind::MissingArgument(name));
 
-        match words.next().unwrap() {
-            "push" | "pop" | "function" | "call" => {
-                words.next().unwrap();
-                words.next().unwrap().parse().unwrap()
+    let command = match argument("command")? {
+        "push" => VmCommand::Push(argument("segment")?.parse()?, number(argument("index")?)?),
+        "pop" => VmCommand::Pop(argument("segment")?.parse()?, number(argument("index")?)?),
+        "label" => VmCommand::Label(argument("label")?.to_owned()),
+        "goto" => VmCommand::Goto(argument("label")?.to_owned()),
+        "if-goto" => VmCommand::IfGoto(argument("label")?.to_owned()),
+        "function" => VmCommand::Function {
+            name: argument("function name")?.to_owned(),
+            n_vars: number(argument("number of locals")?)?,
+        },
+        "call" => VmCommand::Call {
+            name: argument("function name")?.to_owned(),
+            n_args: number(argument("number of arguments")?)?,
+        },
+        "return" => VmCommand::Return,
+        word => match word.parse()? {
+            // only the optimizer produces these
+            Op::Ge | Op::Le | Op::Ne => return Err(ErrorKind::UnknownCommand(word.to_owned())),
+            Op::Mul | Op::Div | Op::Shl | Op::Shr if !vm_extensions => {
+                return Err(ErrorKind::VmExtension(word.to_owned()))
             }
-            _ => panic!("invalid arg2() call at line: {}", line),
-        }
+            op => VmCommand::Arithmetic(op),
+        },
+    };
+
+    if let Some(word) = words.next() {
+        return Err(ErrorKind::UnexpectedArgument(word.to_owned()));
+    }
+
+    Ok(command)
+}
+
+fn number(word: &str) -> Result<u16, ErrorKind> {
+    match word.parse::<u16>() {
+        Ok(n) if n <= 0x7fff => Ok(n),
+        _ => Err(ErrorKind::InvalidNumber(word.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Segment;
+
+    #[test]
+    fn reports_original_line_numbers() {
+        let input = "// Main.vm
+
+function Main.main 0
+    push constant 1 // one
+    pusj constant 2
+
+    pop constant 0
+    push temp 8
+    push local x
+    push
+    add 1
+    return";
+
+        let errors = parse("Main.vm", input).unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "Main.vm:5: unknown command `pusj`",
+                "Main.vm:7: invalid segment `constant`",
+                "Main.vm:8: index 8 is out of range for segment `temp`",
+                "Main.vm:9: `x` is not a number between 0 and 32767",
+                "Main.vm:10: missing segment",
+                "Main.vm:11: unexpected argument `1`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_labels() {
+        let input = "label START
+goto START
+function Main.main 0
+label LOOP
+goto LOOP
+label LOOP
+if-goto END
+function Main.run 0
+label END
+goto START
+goto LOOP";
+
+        let commands = parse("Main.vm", input).unwrap();
+        let errors = check_labels("Main.vm", &commands).unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "Main.vm:6: label `LOOP` is already defined in `Main.main`",
+                "Main.vm:7: label `END` is not defined in `Main.main`",
+                "Main.vm:10: label `START` is not defined in `Main.run`",
+                "Main.vm:11: label `LOOP` is not defined in `Main.run`",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_commands() {
+        let input = include_str!("../../projects/8/FunctionCalls/StaticsTest/Class1.vm");
+        let commands = parse("Class1.vm", input).unwrap();
+
+        let function = VmCommand::Function {
+            name: "Class1.get".into(),
+            n_vars: 0,
+        };
+
+        let expected = [
+            (function, 16),
+            (VmCommand::Push(Segment::Static, 0), 17),
+            (VmCommand::Push(Segment::Static, 1), 18),
+            (VmCommand::Arithmetic(Op::Sub), 19),
+            (VmCommand::Return, 20),
+        ];
+
+        assert_eq!(commands.len(), 12);
+        assert_eq!(
+            commands[7..],
+            expected.map(|(command, line)| SourceCommand { command, line })
+        );
+    }
+
+    #[test]
+    fn vm_extensions_are_opt_in() {
+        let input = "push constant 6
+push constant 7
+mul
+push constant 1
+shr
+ge";
+
+        let errors = parse("Main.vm", input).unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "Main.vm:3: `mul` is a VM extension, enable it with --vm-extensions",
+                "Main.vm:5: `shr` is a VM extension, enable it with --vm-extensions",
+                "Main.vm:6: unknown command `ge`",
+            ]
+        );
+
+        let errors = parse_with("Main.vm", input, true).unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["Main.vm:6: unknown command `ge`"]
+        );
+
+        let commands = parse_with("Main.vm", &input[..input.len() - 3], true).unwrap();
+        assert_eq!(commands[2].command, VmCommand::Arithmetic(Op::Mul));
+        assert_eq!(commands[4].command, VmCommand::Arithmetic(Op::Shr));
     }
 }
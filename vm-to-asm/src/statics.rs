@@ -0,0 +1,90 @@
+use std::collections::BTreeSet;
+
+use crate::command::{Segment, SourceCommand, VmCommand};
+use crate::error::VmError;
+
+/// Static variables live in RAM 16 to 255, below the stack.
+pub const MAX_STATICS: usize = 240;
+
+/// Number of static variables of a class, one per index it uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticUsage<'a> {
+    pub class: &'a str,
+    pub count: usize,
+}
+
+/// The static variables of each .vm file, in the order of the files.
+pub fn usage<'a>(modules: &[(&'a str, Vec<SourceCommand>)]) -> Vec<StaticUsage<'a>> {
+    modules
+        .iter()
+        .map(|(file, commands)| {
+            let indices: BTreeSet<_> = commands
+                .iter()
+                .filter_map(|c| match c.command {
+                    VmCommand::Push(Segment::Static, index)
+                    | VmCommand::Pop(Segment::Static, index) => Some(index),
+                    _ => None,
+                })
+                .collect();
+
+            StaticUsage {
+                class: file.strip_suffix(".vm").unwrap_or(file),
+                count: indices.len(),
+            }
+        })
+        .collect()
+}
+
+/// Checks that the static variables of every class fit in the static segment.
+pub fn check(usage: &[StaticUsage]) -> Result<(), VmError> {
+    let total = usage.iter().map(|u| u.count).sum();
+
+    if total > MAX_STATICS {
+        return Err(VmError::TooManyStatics(total));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn counts_statics_per_class() {
+        let main = parser::parse("Main.vm", "push static 0\npop static 3\npush static 0").unwrap();
+        let ball = parser::parse("Ball.vm", "push constant 1\npop static 239").unwrap();
+        let modules = [("Main.vm", main), ("Ball.vm", ball)];
+
+        let usage = usage(&modules);
+
+        assert_eq!(
+            usage,
+            [
+                StaticUsage {
+                    class: "Main",
+                    count: 2
+                },
+                StaticUsage {
+                    class: "Ball",
+                    count: 1
+                },
+            ]
+        );
+        assert!(check(&usage).is_ok());
+    }
+
+    #[test]
+    fn rejects_too_many_statics() {
+        let input: String = (0..241).map(|i| format!("push static {i}\n")).collect();
+        let modules = [("Main.vm", parser::parse("Main.vm", &input).unwrap())];
+
+        let err = check(&usage(&modules)).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "the program uses 241 static variables, but only 240 fit in RAM 16 to 255"
+        );
+    }
+}